@@ -1,9 +1,9 @@
 use bevy::{
     asset::Handle,
     ecs::{component::Component, system::Resource},
-    input::keyboard::KeyCode,
+    math::Vec2,
     sprite::TextureAtlas,
-    time::Timer,
+    time::{Timer, TimerMode},
 };
 use serde::{Deserialize, Serialize};
 
@@ -17,9 +17,6 @@ pub struct Score(pub usize);
 #[derive(Component)]
 pub struct CheckpointCheck;
 
-#[derive(Component)]
-pub struct LastKeyPressed(pub (KeyCode, usize));
-
 #[derive(Serialize, Deserialize, Component, Copy, Clone, Debug)]
 pub struct Point {
     pub x: f32,
@@ -29,6 +26,10 @@ pub struct Point {
 #[derive(Component)]
 pub struct Player;
 
+// Which local player this is, counting from 0
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlayerNumber(pub usize);
+
 #[derive(Component)]
 pub struct Platform;
 
@@ -38,23 +39,40 @@ pub struct Collision;
 #[derive(Component)]
 pub struct Enemy;
 
+// A gem placed in the map, holds the id of its tiled object
 #[derive(Component)]
-pub struct RightSideOfPlayerCast;
+pub struct Collectible(pub u32);
 
-#[derive(Component)]
-pub struct LeftSideOfPlayerCast;
+// Name of the character players spawn as, saved to the profile when picked
+#[derive(Resource)]
+pub struct SelectedCharacter(pub Option<String>);
 
-#[derive(Component)]
-pub struct BottomOfPlayerRayCast;
+// Ids of the gems picked up since the level was loaded
+#[derive(Resource)]
+pub struct CollectedThisRun(pub Vec<u32>);
 
 #[derive(Component)]
 pub struct ObjectComponent(pub Object);
 
+// Whether the player is standing on something, set from the player's contacts
+#[derive(Component)]
+pub struct Grounded(pub bool);
+
+// Which way is up off of whatever the player is standing on, straight up when
+// in the air
 #[derive(Component)]
-pub struct GroundedCheck;
+pub struct GroundNormal(pub Vec2);
 
+// Which sides of the player are right up against a wall
+#[derive(Component, Default)]
+pub struct TouchingWall {
+    pub left: bool,
+    pub right: bool,
+}
+
+// Runs after a wall jump, until it finishes the player can't grab or jump off a wall again
 #[derive(Component)]
-pub struct SquishCheck;
+pub struct WallJumpLock(pub Timer);
 
 #[derive(Serialize, Deserialize, Component, Clone)]
 pub struct Size {
@@ -102,5 +120,38 @@ pub struct OldMap {
     pub platforms: Vec<Object>,
 }
 
-#[derive(Resource)]
+#[derive(Component)]
 pub struct LastJumpTime(pub Timer);
+
+// Lets a jump through when it is pressed a little after walking off a ledge
+// (coyote time) or a little before landing (jump buffer)
+#[derive(Component)]
+pub struct JumpWindows {
+    // counts up from the last frame the player was grounded
+    pub coyote: Timer,
+    // counts up from the last time jump was pressed
+    pub buffer: Timer,
+    // the player should leave the ground this frame
+    pub take_off: bool,
+}
+
+impl JumpWindows {
+    // Both windows start out closed
+    pub fn new(coyote_secs: f32, buffer_secs: f32) -> JumpWindows {
+        let mut jump_windows = JumpWindows {
+            coyote: Timer::from_seconds(coyote_secs, TimerMode::Once),
+            buffer: Timer::from_seconds(buffer_secs, TimerMode::Once),
+            take_off: false,
+        };
+        jump_windows.close();
+        jump_windows
+    }
+
+    // Runs both timers out so neither lets another jump through
+    pub fn close(&mut self) {
+        let coyote_duration = self.coyote.duration();
+        self.coyote.tick(coyote_duration);
+        let buffer_duration = self.buffer.duration();
+        self.buffer.tick(buffer_duration);
+    }
+}
@@ -8,8 +8,18 @@ use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
 use plugins::animation_manager::AnimationManager;
 use plugins::config_loader::ConfigLoader;
 use plugins::delete_manager::DeleteManager;
+use plugins::input_manager::InputManager;
+use plugins::replay::ReplayPlayback;
 
-use scenes::{home::HomeScene, level::LevelScene, map::MapScene, Scene};
+use scenes::{
+    character_select::CharacterSelectScene,
+    controls::ControlsScene,
+    editor::EditorScene,
+    home::HomeScene,
+    level::{LevelID, LevelScene},
+    map::MapScene,
+    Scene,
+};
 pub struct Game;
 impl Plugin for Game {
     fn build(&self, app: &mut App) {
@@ -20,14 +30,56 @@ impl Plugin for Game {
             },
             AnimationManager,
             DeleteManager,
+            InputManager,
         ))
         .add_state::<Scene>()
         .add_plugins(HomeScene)
         .add_plugins(MapScene)
-        .add_plugins(LevelScene);
+        .add_plugins(LevelScene)
+        .add_plugins(EditorScene)
+        .add_plugins(ControlsScene)
+        .add_plugins(CharacterSelectScene);
     }
 }
 
 fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    // `jumper generate <seed> [chunks]` writes a generated level into assets
+    if args.get(1).map(|arg| arg.as_str()) == Some("generate") {
+        let seed = args
+            .get(2)
+            .and_then(|seed| seed.parse::<u64>().ok())
+            .expect("usage: jumper generate <seed> [chunks]");
+        let chunk_count = args
+            .get(3)
+            .and_then(|chunks| chunks.parse::<u32>().ok())
+            .unwrap_or(20);
+        let level_id = service::level_generator::write_generated_level(seed, chunk_count);
+        println!("wrote ./assets/{}", level_id);
+        return;
+    }
+    // `jumper analyze <level>` checks that everything in a level can be reached
+    if args.get(1).map(|arg| arg.as_str()) == Some("analyze") {
+        let level_id = args.get(2).expect("usage: jumper analyze <level>");
+        let report = service::level_analysis::analyze_level(level_id);
+        service::level_analysis::print_report(level_id, &report);
+        if report.has_problems() {
+            std::process::exit(1);
+        }
+        return;
+    }
+    // `jumper replay <file>` plays a recorded run back and checks it ends the same way
+    if args.get(1).map(|arg| arg.as_str()) == Some("replay") {
+        let file_path = args.get(2).expect("usage: jumper replay <file>");
+        let replay = service::replay::Replay::load_from_file(file_path)
+            .unwrap_or_else(|| panic!("couldn't read replay {}", file_path));
+        App::new()
+            .add_plugins(Game)
+            .insert_resource(LevelID(replay.level_id.clone()))
+            .insert_resource(ReplayPlayback::new(replay))
+            .insert_resource(NextState(Some(Scene::Level)))
+            .run();
+        return;
+    }
     App::new().add_plugins(Game).run();
 }
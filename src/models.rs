@@ -1,63 +1,129 @@
-use bevy::{
-    ecs::{component::Component, system::Resource},
-    input::keyboard::KeyCode,
-};
+use bevy::ecs::{component::Component, system::Resource};
 use serde::{Deserialize, Serialize};
 
-use crate::scenes::Scene;
+use crate::{plugins::player_state::PlayerState, scenes::Scene, service::bindings::Action};
 
 // Used to define a single trick
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TrickDefinition {
     pub name: String,
     pub points: usize,
     pub takes_ms: usize,
+    #[serde(default)]
+    pub animation: Option<TrickAnimation>,
+    #[serde(default)]
+    pub effects: TrickEffects,
+}
+
+// How the player looks while doing a trick, played once over takes_ms
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum TrickAnimation {
+    // frames counted from the first frame of the character's Trick animation, inclusive
+    Frames { start: usize, end: usize },
+    // turns the player all the way round is 360, positive is a front flip
+    Rotation { degrees: f32 },
+}
+
+// What a trick does to the player's movement
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TrickEffects {
+    // added to the velocity when the trick starts, x is in the way the player faces
+    #[serde(default)]
+    pub boost_x: f32,
+    #[serde(default)]
+    pub boost_y: f32,
+    // secs at the start of the trick the player hangs in the air for
+    #[serde(default)]
+    pub hang_time: f32,
 }
 
 // Represents all possible tricks for the game
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TrickList {
-    pub tricks: Vec<(Vec<KeyCode>, TrickDefinition)>,
+    pub tricks: Vec<(Vec<Action>, TrickDefinition)>,
+}
+
+#[derive(Component)]
+pub struct BelongsToScene(pub Scene);
+
+// Frames of the character sheet to loop through, inclusive
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct AnimationRange {
+    pub start: usize,
+    pub end: usize,
+    pub ms_per_frame: f32,
 }
-impl TrickList {
-    pub fn find_trick(&mut self, keys: &Vec<KeyCode>) -> Option<&TrickDefinition> {
-        let filtered = self
-            .tricks
+
+// Which animation plays for each player state
+#[derive(Serialize, Deserialize, Component, Debug, Clone)]
+pub struct PlayerAnimations {
+    pub default: AnimationRange,
+    pub states: Vec<(PlayerState, AnimationRange)>,
+}
+impl PlayerAnimations {
+    pub fn for_state(&self, state: PlayerState) -> AnimationRange {
+        self.states
             .iter()
-            .filter(|(trick_keys, trick_definition)| {
-                if trick_keys.len() > keys.len() {
-                    return true;
-                }
-                if trick_keys.len() != keys.len() {
-                    return false;
-                }
-                for i in 0..trick_keys.len() {
-                    if trick_keys[i] != keys[i] {
-                        return false;
-                    }
-                }
-                true
-            })
-            .map(|(_, trick_definition)| trick_definition);
-        let filtered = filtered.collect::<Vec<&TrickDefinition>>();
-        match filtered.len() {
-            1 => {
-                let trick = filtered[0];
-                return Some(trick);
-            }
-            _ => {
-                return None;
-            }
+            .find(|(animated_state, _)| *animated_state == state)
+            .map(|(_, range)| *range)
+            .unwrap_or(self.default)
+    }
+}
+
+// Multipliers on the movement constants, 1 leaves a stat as it is
+#[derive(Serialize, Deserialize, Component, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct StatModifiers {
+    pub run_speed: f32,
+    pub max_speed: f32,
+    pub jump_force: f32,
+}
+impl Default for StatModifiers {
+    fn default() -> Self {
+        Self {
+            run_speed: 1.,
+            max_speed: 1.,
+            jump_force: 1.,
         }
     }
 }
 
-// Resource used to track all possible tricks for the game
-#[derive(Serialize, Deserialize, Resource, Debug, Clone)]
-pub struct TrickListResource(pub TrickList);
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ColliderSize {
+    pub width: f32,
+    pub height: f32,
+}
 
-#[derive(Component)]
-pub struct BelongsToScene(pub Scene);
+// A playable character, all of its frames come from the character sheet
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CharacterDefinition {
+    pub name: String,
+    pub collider: ColliderSize,
+    #[serde(default)]
+    pub modifiers: StatModifiers,
+    pub animations: PlayerAnimations,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CharacterList {
+    pub characters: Vec<CharacterDefinition>,
+}
+impl CharacterList {
+    // The character with the given name, or the first one when there is no
+    // such character
+    pub fn find(&self, name: Option<&str>) -> &CharacterDefinition {
+        name.and_then(|name| {
+            self.characters
+                .iter()
+                .find(|character| character.name == name)
+        })
+        .unwrap_or(&self.characters[0])
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct CharacterListResource(pub CharacterList);
 
 #[derive(Resource, Debug, Clone, Serialize, Deserialize)]
 pub struct ElementBars {
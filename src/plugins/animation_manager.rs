@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use bevy::{
     app::{App, Plugin, Update},
     ecs::{
@@ -23,11 +25,27 @@ impl LoopingIncrementer {
         }
         self.current
     }
+
+    // Switches to looping a different range, starting from its first frame
+    pub fn restart(&mut self, start: usize, end: usize) -> usize {
+        self.start = start;
+        self.end = end;
+        self.current = start;
+        self.current
+    }
 }
 
 #[derive(Component)]
 pub struct AnimationTimer(Timer);
 
+impl AnimationTimer {
+    pub fn set_ms_per_frame(&mut self, ms_per_frame: f32) {
+        self.0
+            .set_duration(Duration::from_secs_f32(ms_per_frame / 1000.));
+        self.0.reset();
+    }
+}
+
 pub struct SpriteAnimationController;
 impl SpriteAnimationController {
     pub fn new(
@@ -49,7 +67,7 @@ impl SpriteAnimationController {
     }
 }
 
-fn update_animated_sprites(
+pub fn update_animated_sprites(
     time: Res<Time>,
     mut query: Query<(
         &mut AnimationTimer,
@@ -4,17 +4,26 @@ use bevy::{
     ecs::{
         query::{With, Without},
         schedule::{
-            apply_deferred, common_conditions::in_state, IntoSystemConfigs, ScheduleLabel, State,
-            SystemConfigs,
+            apply_deferred,
+            common_conditions::{in_state, not, resource_exists},
+            IntoSystemConfigs, ScheduleLabel, State, SystemConfigs,
         },
         system::{Commands, Query, Res, System},
     },
+    math::Vec2,
     render::camera::OrthographicProjection,
     transform::components::Transform,
     utils::{default, intern::Interned},
+    window::{PrimaryWindow, Window},
 };
 
-use crate::{components_resources::Player, models::BelongsToScene, scenes::Scene};
+use crate::{
+    components_resources::Player,
+    models::BelongsToScene,
+    scenes::{editor::Editor, Scene},
+};
+
+use super::{health::Lives, physics::interpolate_physics_transforms};
 
 // inserts a camera bundle into our app
 fn insert_camera(mut commands: Commands, scene: Res<State<Scene>>) {
@@ -24,24 +33,52 @@ fn insert_camera(mut commands: Commands, scene: Res<State<Scene>>) {
     ));
 }
 
+// How far the camera is zoomed in when everyone fits on screen
+const BASE_SCALE: f32 = 1. / 2.5;
+// Room kept around the outermost players so they aren't right on the edge
+const FRAMING_MARGIN: f32 = 96.;
+
 // initializes the cameras settings
 fn adjust_camera(mut camera_query: Query<&mut OrthographicProjection, With<Camera2d>>) {
     if let Some(mut projection) = camera_query.iter_mut().next() {
-        projection.scale /= 2.5;
+        projection.scale = BASE_SCALE;
     }
 }
 
-// Updates the camera position to the players position
+// Players, kept apart from the camera's own transform
+type PlayerFilter = (With<Player>, Without<Camera2d>);
+
+// Centers the camera on the players still in the game and zooms out when
+// they get too far apart to fit at the normal zoom
 fn follow_player(
-    mut camera_query: Query<&mut Transform, With<Camera2d>>,
-    player_query: Query<&Transform, (With<Player>, Without<Camera2d>)>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<Camera2d>>,
+    player_query: Query<(&Transform, &Lives), PlayerFilter>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
 ) {
-    player_query.iter().next().and_then(|player_transform| {
-        camera_query.iter_mut().next().map(|mut camera_transform| {
-            camera_transform.translation.x = player_transform.translation.x;
-            camera_transform.translation.y = player_transform.translation.y;
-        })
-    });
+    let positions = player_query
+        .iter()
+        .filter(|(_, lives)| lives.0 > 0)
+        .map(|(transform, _)| transform.translation.truncate())
+        .collect::<Vec<_>>();
+    let Some(first) = positions.first() else {
+        return;
+    };
+    let (min, max) = positions
+        .iter()
+        .fold((*first, *first), |(min, max), position| {
+            (min.min(*position), max.max(*position))
+        });
+    let Some((mut camera_transform, mut projection)) = camera_query.iter_mut().next() else {
+        return;
+    };
+    let center = (min + max) / 2.;
+    camera_transform.translation.x = center.x;
+    camera_transform.translation.y = center.y;
+    if let Ok(window) = window_query.get_single() {
+        let needed = (max - min + Vec2::splat(FRAMING_MARGIN * 2.))
+            / Vec2::new(window.width(), window.height());
+        projection.scale = BASE_SCALE.max(needed.max_element());
+    }
 }
 
 pub struct CameraControls {
@@ -54,6 +91,13 @@ impl Plugin for CameraControls {
             self.startup,
             (insert_camera, apply_deferred, adjust_camera).chain(),
         );
-        app.add_systems(Update, follow_player.run_if(in_state(self.scene)));
+        app.add_systems(
+            Update,
+            follow_player
+                .after(interpolate_physics_transforms)
+                .run_if(in_state(self.scene))
+                // the editor moves the camera around itself
+                .run_if(not(resource_exists::<Editor>())),
+        );
     }
 }
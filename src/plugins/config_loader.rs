@@ -1,12 +1,16 @@
 use bevy::{
     app::{App, Plugin, PreStartup},
-    ecs::{schedule::ScheduleLabel, system::Commands},
+    ecs::{
+        schedule::{apply_deferred, IntoSystemConfigs, ScheduleLabel},
+        system::{Commands, Res},
+    },
     utils::intern::Interned,
 };
 
 use crate::{
-    models::{TrickList, TrickListResource},
-    service::{constants::Constants, user_stats},
+    components_resources::SelectedCharacter,
+    models::{CharacterList, CharacterListResource, TrickList},
+    service::{bindings::Bindings, constants::Constants, trick_trie::TrickTrie, user_stats},
 };
 
 fn initialize_constants(mut commands: Commands) {
@@ -18,23 +22,34 @@ fn initialize_constants(mut commands: Commands) {
 
 fn initialize_trick_list(mut commands: Commands) {
     let raw = std::fs::read_to_string("./assets/trick_list.json").unwrap();
-    let mut trick_list = serde_json::from_str::<TrickList>(&raw).unwrap();
-    // handle longer tricks first so that we don't accidentally
-    // trigger a shorter trick when we're trying to do a longer one
-    trick_list
-        .tricks
-        .sort_by(|(keys_a, _), (keys_b, _)| keys_b.len().cmp(&keys_a.len()));
-    commands.insert_resource(TrickListResource(trick_list));
+    let trick_list = serde_json::from_str::<TrickList>(&raw).unwrap();
+    // tricks that start the same way are told apart as the keys come in
+    commands.insert_resource(TrickTrie::new(&trick_list.tricks));
+}
+
+fn initialize_characters(mut commands: Commands) {
+    let raw = std::fs::read_to_string("./assets/characters.json").unwrap();
+    let characters = serde_json::from_str::<CharacterList>(&raw).unwrap();
+    commands.insert_resource(CharacterListResource(characters));
+    let selected = user_stats::UserStats::load_from_file().and_then(|stats| stats.character);
+    commands.insert_resource(SelectedCharacter(selected));
+}
+
+fn initialize_bindings(mut commands: Commands, constants: Res<Constants>) {
+    commands.insert_resource(Bindings::load_from_file(&constants).unwrap_or_default());
 }
 
 pub struct ConfigLoader {
     pub pre_startup: Interned<dyn ScheduleLabel>,
 }
 
-fn write_data_files() {
+fn write_data_files(constants: Res<Constants>) {
     if let None = user_stats::UserStats::load_from_file() {
         user_stats::create_user_stats_file();
     }
+    if Bindings::load_from_file(&constants).is_none() {
+        Bindings::default().save_to_file(&constants);
+    }
 }
 
 impl Plugin for ConfigLoader {
@@ -43,8 +58,14 @@ impl Plugin for ConfigLoader {
             PreStartup,
             (
                 initialize_trick_list,
-                initialize_constants,
-                write_data_files,
+                initialize_characters,
+                // the bindings file lives where the constants say
+                (
+                    initialize_constants,
+                    apply_deferred,
+                    (initialize_bindings, write_data_files),
+                )
+                    .chain(),
             ),
         );
     }
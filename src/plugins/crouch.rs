@@ -0,0 +1,129 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        query::With,
+        system::{Query, Res},
+    },
+    math::Vec2,
+    sprite::{Anchor, TextureAtlasSprite},
+    transform::components::Transform,
+};
+use bevy_xpbd_2d::{
+    components::Collider,
+    plugins::spatial_query::{SpatialQuery, SpatialQueryFilter},
+};
+
+use crate::{
+    components_resources::{JumpWindows, Player},
+    service::constants::Constants,
+};
+
+use super::{
+    physics::{Layers, PhysicsInterpolation},
+    player_state::PlayerState,
+};
+
+// Keeps the standing up check from catching the floor the player is on
+const HEADROOM_SKIN: f32 = 1.;
+
+// Whether the player has shrunk down to crouch or slide, and whether there is
+// room above them to stand back up
+#[derive(Component)]
+pub struct Crouch {
+    // the collider size the player stands at
+    standing: Vec2,
+    sprite_height: f32,
+    pub crouched: bool,
+    pub room_to_stand: bool,
+}
+impl Crouch {
+    pub fn new(standing: Vec2, sprite_height: f32) -> Self {
+        Self {
+            standing,
+            sprite_height,
+            crouched: false,
+            room_to_stand: true,
+        }
+    }
+
+    fn crouched_height(&self, constants: &Constants) -> f32 {
+        self.standing.y * constants.crouch_height
+    }
+}
+
+// Looks for anything solid where the player's head would be if they stood up,
+// a crouching player can't jump or stand up until it's clear
+pub fn check_headroom(
+    constants: Res<Constants>,
+    spatial_query: SpatialQuery,
+    mut player_query: Query<(&Transform, &mut Crouch, &mut JumpWindows), With<Player>>,
+) {
+    player_query
+        .iter_mut()
+        .for_each(|(transform, mut crouch, mut jump_windows)| {
+            if !crouch.crouched {
+                crouch.room_to_stand = true;
+                return;
+            }
+            let rise = (crouch.standing.y - crouch.crouched_height(&constants)) / 2.;
+            let standing_center = transform.translation.truncate() + Vec2::new(0., rise);
+            crouch.room_to_stand = spatial_query
+                .shape_intersections(
+                    &Collider::cuboid(
+                        crouch.standing.x - HEADROOM_SKIN * 2.,
+                        crouch.standing.y - HEADROOM_SKIN * 2.,
+                    ),
+                    standing_center,
+                    0.,
+                    SpatialQueryFilter::new().with_masks([Layers::Ground]),
+                )
+                .is_empty();
+            if !crouch.room_to_stand {
+                jump_windows.take_off = false;
+            }
+        });
+}
+
+// Everything that changes shape when a player crouches or stands back up
+type CrouchColliderQuery = (
+    &'static mut Crouch,
+    &'static mut Collider,
+    &'static mut Transform,
+    &'static mut PhysicsInterpolation,
+    &'static mut TextureAtlasSprite,
+    &'static PlayerState,
+);
+
+// Swaps between the standing and crouched collider when the player's state
+// calls for it. The feet stay where they are, the player and sprite are moved
+// so only the top of the collider moves
+pub fn resize_crouch_collider(
+    constants: Res<Constants>,
+    mut player_query: Query<CrouchColliderQuery, With<Player>>,
+) {
+    player_query.iter_mut().for_each(
+        |(mut crouch, mut collider, mut transform, mut interpolation, mut sprite, state)| {
+            let should_crouch = matches!(state, PlayerState::Crouch | PlayerState::Slide);
+            if should_crouch == crouch.crouched {
+                return;
+            }
+            crouch.crouched = should_crouch;
+            let crouched_height = crouch.crouched_height(&constants);
+            let shift = (crouch.standing.y - crouched_height) / 2.;
+            let (height, shift) = if should_crouch {
+                (crouched_height, -shift)
+            } else {
+                (crouch.standing.y, shift)
+            };
+            *collider = Collider::cuboid(crouch.standing.x, height);
+            transform.translation.y += shift;
+            interpolation.teleport(transform.translation.truncate());
+            // keep the sprite drawn where it was before the player moved
+            sprite.anchor = if should_crouch {
+                Anchor::Custom(Vec2::new(0., shift / crouch.sprite_height))
+            } else {
+                Anchor::Center
+            };
+        },
+    );
+}
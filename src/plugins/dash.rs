@@ -0,0 +1,215 @@
+use bevy::{
+    asset::Handle,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::With,
+        schedule::State,
+        system::{Commands, Query, Res},
+    },
+    input::Input,
+    math::{Vec2, Vec3},
+    sprite::{SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
+    time::{Time, Timer, TimerMode},
+    transform::components::Transform,
+};
+use bevy_xpbd_2d::components::{GravityScale, LinearVelocity};
+
+use crate::{
+    components_resources::{Grounded, Player},
+    models::{BelongsToScene, StatModifiers},
+    scenes::Scene,
+    service::{bindings::Action, constants::Constants},
+};
+
+use super::{
+    delete_manager::DeleteMe,
+    input_manager::FixedActions,
+    player_manager::finished_timer,
+    player_state::{Facing, PlayerState},
+};
+
+// How often a trail image is left behind while dashing
+const TRAIL_INTERVAL_SECS: f32 = 0.03;
+// How long a trail image takes to fade out
+const TRAIL_FADE_SECS: f32 = 0.2;
+
+#[derive(Component)]
+pub struct Dash {
+    // runs while the dash is happening
+    active: Timer,
+    cooldown: Timer,
+    trail: Timer,
+    direction: Vec2,
+    air_dashes_left: u32,
+}
+impl Dash {
+    pub fn new(constants: &Constants) -> Self {
+        Self {
+            active: finished_timer(constants.dash_time),
+            cooldown: finished_timer(constants.dash_cooldown),
+            trail: Timer::from_seconds(TRAIL_INTERVAL_SECS, TimerMode::Repeating),
+            direction: Vec2::ZERO,
+            air_dashes_left: constants.air_dashes,
+        }
+    }
+    pub fn is_dashing(&self) -> bool {
+        !self.active.finished()
+    }
+}
+
+// An afterimage left behind by a dash, fades out then gets deleted
+#[derive(Component)]
+pub struct DashTrail(Timer);
+
+// Which way the held directions point, in 8 directions
+fn held_direction(actions: &Input<Action>) -> Vec2 {
+    let x = match (
+        actions.pressed(Action::MoveLeft),
+        actions.pressed(Action::MoveRight),
+    ) {
+        (true, false) => -1.,
+        (false, true) => 1.,
+        _ => 0.,
+    };
+    let y = match (
+        actions.pressed(Action::AimDown),
+        actions.pressed(Action::AimUp),
+    ) {
+        (true, false) => -1.,
+        (false, true) => 1.,
+        _ => 0.,
+    };
+    Vec2::new(x, y).normalize_or_zero()
+}
+
+type DashQuery = (
+    &'static mut Dash,
+    &'static mut LinearVelocity,
+    &'static mut GravityScale,
+    &'static Grounded,
+    &'static Facing,
+    &'static PlayerState,
+    &'static FixedActions,
+    &'static StatModifiers,
+);
+
+// Starts dashes and keeps the player moving in a straight line while one is
+// going, gravity is turned off for the player until it ends
+pub fn update_dash(
+    time: Res<Time>,
+    constants: Res<Constants>,
+    mut player_query: Query<DashQuery, With<Player>>,
+) {
+    player_query.iter_mut().for_each(
+        |(
+            mut dash,
+            mut velocity,
+            mut gravity_scale,
+            grounded,
+            facing,
+            state,
+            actions,
+            modifiers,
+        )| {
+            dash.cooldown.tick(time.delta());
+            if grounded.0 && !dash.is_dashing() {
+                dash.air_dashes_left = constants.air_dashes;
+            }
+            if dash.is_dashing() {
+                dash.active.tick(time.delta());
+                if dash.active.finished() {
+                    gravity_scale.0 = 1.;
+                    // don't carry the whole dash speed out of it
+                    velocity.0 = velocity
+                        .0
+                        .clamp_length_max(constants.max_player_speed * modifiers.max_speed);
+                    return;
+                }
+                velocity.0 = dash.direction * constants.dash_speed;
+                return;
+            }
+            if !actions.just_pressed(Action::Dash)
+                || !state.has_control()
+                || state.on_ledge()
+                || !dash.cooldown.finished()
+                || (!grounded.0 && dash.air_dashes_left == 0)
+            {
+                return;
+            }
+            if !grounded.0 {
+                dash.air_dashes_left -= 1;
+            }
+            let direction = held_direction(actions);
+            dash.direction = if direction == Vec2::ZERO {
+                match facing {
+                    Facing::Left => Vec2::NEG_X,
+                    Facing::Right => Vec2::X,
+                }
+            } else {
+                direction
+            };
+            dash.active.reset();
+            dash.cooldown.reset();
+            dash.trail.reset();
+            gravity_scale.0 = 0.;
+            velocity.0 = dash.direction * constants.dash_speed;
+        },
+    );
+}
+
+pub fn spawn_dash_trail(
+    mut commands: Commands,
+    time: Res<Time>,
+    scene: Res<State<Scene>>,
+    mut player_query: Query<
+        (
+            &mut Dash,
+            &Transform,
+            &TextureAtlasSprite,
+            &Handle<TextureAtlas>,
+        ),
+        With<Player>,
+    >,
+) {
+    player_query
+        .iter_mut()
+        .for_each(|(mut dash, transform, sprite, texture_atlas)| {
+            if !dash.is_dashing() {
+                return;
+            }
+            dash.trail.tick(time.delta());
+            if !dash.trail.just_finished() {
+                return;
+            }
+            let mut trail_sprite = sprite.clone();
+            trail_sprite.color.set_a(0.5);
+            commands.spawn((
+                BelongsToScene(**scene),
+                DashTrail(Timer::from_seconds(TRAIL_FADE_SECS, TimerMode::Once)),
+                SpriteSheetBundle {
+                    // just behind the player
+                    transform: transform.with_translation(transform.translation - Vec3::Z),
+                    sprite: trail_sprite,
+                    texture_atlas: texture_atlas.clone(),
+                    ..Default::default()
+                },
+            ));
+        });
+}
+
+pub fn fade_dash_trail(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut trail_query: Query<(Entity, &mut DashTrail, &mut TextureAtlasSprite)>,
+) {
+    trail_query
+        .iter_mut()
+        .for_each(|(entity, mut trail, mut sprite)| {
+            trail.0.tick(time.delta());
+            sprite.color.set_a(0.5 * trail.0.percent_left());
+            if trail.0.finished() {
+                commands.entity(entity).insert(DeleteMe);
+            }
+        });
+}
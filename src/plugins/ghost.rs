@@ -0,0 +1,198 @@
+use bevy::{
+    app::{App, FixedUpdate, Plugin},
+    ecs::{
+        component::Component,
+        query::With,
+        schedule::{
+            common_conditions::{in_state, resource_exists},
+            IntoSystemConfigs, OnEnter, OnExit, State,
+        },
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    log::info,
+    math::Vec2,
+    render::{color::Color, view::Visibility},
+    sprite::{SpriteSheetBundle, TextureAtlasSprite},
+    transform::components::Transform,
+};
+use bevy_xpbd_2d::PhysicsSet;
+
+use crate::{
+    components_resources::{Player, PlayerNumber, TextureAtlasHandle, TilesetName},
+    models::BelongsToScene,
+    scenes::{
+        level::{LevelID, LevelStopwatch},
+        Scene,
+    },
+    service::{
+        constants::Constants,
+        ghost::{GhostFrame, GhostPath, GhostRun},
+        user_stats::{LevelResult, UserStats},
+    },
+};
+
+use super::{
+    physics::{GameplaySet, PhysicsInterpolation},
+    player_manager::initialize_player,
+    replay::{advance_replay_step, ReplayRecorder, ReplayStep},
+};
+
+const GHOST_TINT: Color = Color::rgba(1., 1., 1., 0.4);
+
+// The path of the run being played, saved as the new ghost if it's the fastest
+#[derive(Resource)]
+pub struct GhostRecorder(GhostRun);
+
+// Follows one player's path from the best run of the level
+#[derive(Component)]
+struct Ghost(GhostPath);
+
+fn start_ghost(
+    mut commands: Commands,
+    constants: Res<Constants>,
+    level_id: Res<LevelID>,
+    scene: Res<State<Scene>>,
+    replay_recorder: Option<Res<ReplayRecorder>>,
+    atlas_query: Query<(&TextureAtlasHandle, &TilesetName)>,
+) {
+    // only runs that get recorded as the player's own can become the ghost
+    if replay_recorder.is_some() {
+        commands.insert_resource(GhostRecorder(GhostRun {
+            level_id: level_id.0.clone(),
+            time: 0,
+            players: vec![],
+        }));
+    }
+    if UserStats::load_from_file().unwrap_or_default().hide_ghosts {
+        return;
+    }
+    let Some(best) = GhostRun::load(&constants, &level_id.0) else {
+        return;
+    };
+    let Some((atlas, _)) = atlas_query
+        .iter()
+        .find(|(_, name)| name.0 == constants.character_sheet)
+    else {
+        return;
+    };
+    best.players.into_iter().for_each(|path| {
+        let Some(first) = path.frames.first().copied() else {
+            return;
+        };
+        let position = Vec2::new(first.x, first.y);
+        let mut sprite = TextureAtlasSprite::new(first.sprite);
+        sprite.color = GHOST_TINT;
+        commands.spawn((
+            BelongsToScene(*scene.get()),
+            SpriteSheetBundle {
+                transform: Transform::from_translation(position.extend(0.)),
+                sprite,
+                texture_atlas: atlas.0.clone(),
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+            PhysicsInterpolation::new(position),
+            Ghost(path),
+        ));
+    });
+}
+
+fn record_ghost(
+    step: Res<ReplayStep>,
+    level_stopwatch: Res<LevelStopwatch>,
+    mut recorder: ResMut<GhostRecorder>,
+    player_query: Query<(&PlayerNumber, &Transform, &TextureAtlasSprite), With<Player>>,
+) {
+    if level_stopwatch.finished() {
+        return;
+    }
+    let mut players = player_query.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(number, _, _)| number.0);
+    players.iter().for_each(|(number, transform, sprite)| {
+        if number.0 >= recorder.0.players.len() {
+            recorder.0.players.push(GhostPath {
+                start_step: step.0,
+                frames: vec![],
+            });
+        }
+        recorder.0.players[number.0].frames.push(GhostFrame {
+            x: transform.translation.x,
+            y: transform.translation.y,
+            sprite: sprite.index,
+            flip_x: sprite.flip_x,
+        });
+    });
+}
+
+// Ghosts go where the best run was on the same step, and disappear before
+// that player joined and after the run finished
+fn move_ghosts(
+    step: Res<ReplayStep>,
+    mut ghost_query: Query<(
+        &Ghost,
+        &mut Transform,
+        &mut PhysicsInterpolation,
+        &mut TextureAtlasSprite,
+        &mut Visibility,
+    )>,
+) {
+    ghost_query.iter_mut().for_each(
+        |(ghost, mut transform, mut interpolation, mut sprite, mut visibility)| {
+            let Some(frame) = ghost.0.frame_at(step.0) else {
+                *visibility = Visibility::Hidden;
+                return;
+            };
+            transform.translation.x = frame.x;
+            transform.translation.y = frame.y;
+            // don't slide in from wherever it was hidden
+            if *visibility == Visibility::Hidden {
+                interpolation.teleport(Vec2::new(frame.x, frame.y));
+                *visibility = Visibility::Inherited;
+            }
+            sprite.index = frame.sprite;
+            sprite.flip_x = frame.flip_x;
+        },
+    );
+}
+
+pub fn save_ghost_on_completed(
+    mut commands: Commands,
+    constants: Res<Constants>,
+    level_result: Res<LevelResult>,
+    recorder: Option<ResMut<GhostRecorder>>,
+) {
+    let Some(mut recorder) = recorder else {
+        return;
+    };
+    recorder.0.time = level_result.time;
+    if recorder.0.save_if_best(&constants) {
+        info!("new ghost for {}", recorder.0.level_id);
+    }
+    commands.remove_resource::<GhostRecorder>();
+}
+
+fn stop_ghost(mut commands: Commands) {
+    commands.remove_resource::<GhostRecorder>();
+}
+
+pub struct GhostManager {
+    pub scene: Scene,
+}
+impl Plugin for GhostManager {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(self.scene), start_ghost.after(initialize_player));
+        app.add_systems(
+            FixedUpdate,
+            (
+                move_ghosts.in_set(GameplaySet),
+                // where physics left the players at the end of the step
+                record_ghost
+                    .after(PhysicsSet::Sync)
+                    .before(advance_replay_step)
+                    .run_if(resource_exists::<GhostRecorder>()),
+            )
+                .run_if(in_state(self.scene)),
+        );
+        app.add_systems(OnExit(self.scene), stop_ghost);
+    }
+}
@@ -0,0 +1,234 @@
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{Event, EventWriter},
+        query::{With, Without},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Query, Res},
+    },
+    math::Vec2,
+    sprite::TextureAtlasSprite,
+    time::{Time, Timer},
+    transform::components::Transform,
+};
+use bevy_xpbd_2d::{
+    components::{Collider, GravityScale, LinearVelocity},
+    plugins::collision::Collisions,
+};
+
+use crate::{
+    components_resources::{Enemy, Player},
+    scenes::Scene,
+    service::constants::Constants,
+};
+
+use super::{
+    delete_manager::DeleteMe,
+    physics::{GameplaySet, PhysicsInterpolation},
+    player_state::{update_player_state, ForcePlayerState, PlayerState, TimeInState},
+    stomp::{stomp_enemies, StompCombo},
+};
+
+// How close the player has to be to an enemy for it to count as touching
+const CONTACT_MARGIN: f32 = 2.;
+// How long each on/off of the invulnerability blink lasts
+const BLINK_SECS: f32 = 0.1;
+
+#[derive(Component)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+#[derive(Component)]
+pub struct Lives(pub u32);
+
+// Runs after the player gets hit, enemies can't hurt them until it finishes
+#[derive(Component)]
+pub struct Invulnerable(pub Timer);
+
+// Where the player comes back after losing a life
+#[derive(Component)]
+pub struct SpawnPoint(pub Vec2);
+
+// Sent when the player dies with no lives left
+#[derive(Event)]
+pub struct OutOfLives;
+
+fn half_extents(collider: &Collider) -> Vec2 {
+    let half_extents = collider.shape().as_cuboid().unwrap().half_extents;
+    Vec2::new(half_extents[0], half_extents[1])
+}
+
+// Enemies that were stomped this step are on their way out and can't hurt
+type LiveEnemy = (With<Enemy>, Without<Player>, Without<DeleteMe>);
+
+type DamageQuery = (
+    Entity,
+    &'static Transform,
+    &'static Collider,
+    &'static mut LinearVelocity,
+    &'static mut Health,
+    &'static mut Invulnerable,
+    &'static PlayerState,
+    &'static StompCombo,
+);
+
+fn damage_player_on_enemy_contact(
+    time: Res<Time>,
+    constants: Res<Constants>,
+    collisions: Res<Collisions>,
+    mut force_state_events: EventWriter<ForcePlayerState>,
+    mut player_query: Query<DamageQuery, With<Player>>,
+    enemy_query: Query<(Entity, &Transform, &Collider), LiveEnemy>,
+) {
+    player_query.iter_mut().for_each(
+        |(
+            entity,
+            transform,
+            collider,
+            mut velocity,
+            mut health,
+            mut invulnerable,
+            state,
+            combo,
+        )| {
+            invulnerable.0.tick(time.delta());
+            if !invulnerable.0.finished() || *state == PlayerState::Dead {
+                return;
+            }
+            let player_half = half_extents(collider);
+            let player_position = transform.translation.truncate();
+            let touched_enemy = enemy_query
+                .iter()
+                // coming down on top of an enemy squishes it instead
+                .filter(|(enemy, _, _)| !combo.landed_on(&collisions, entity, *enemy))
+                .find(|(_, enemy_transform, enemy_collider)| {
+                    let enemy_half = half_extents(enemy_collider);
+                    let offset = player_position - enemy_transform.translation.truncate();
+                    offset.x.abs() < player_half.x + enemy_half.x + CONTACT_MARGIN
+                        && offset.y.abs() < player_half.y + enemy_half.y + CONTACT_MARGIN
+                });
+            let Some((_, enemy_transform, _)) = touched_enemy else {
+                return;
+            };
+            health.current = health.current.saturating_sub(1);
+            invulnerable.0.reset();
+            let away_from_enemy = if player_position.x < enemy_transform.translation.x {
+                -1.
+            } else {
+                1.
+            };
+            velocity.0 = Vec2::new(
+                away_from_enemy * constants.knockback_force_x,
+                constants.knockback_force_y,
+            );
+            force_state_events.send(ForcePlayerState {
+                entity,
+                state: if health.current == 0 {
+                    PlayerState::Dead
+                } else {
+                    PlayerState::Hurt
+                },
+            });
+        },
+    );
+}
+
+fn blink_invulnerable_player(
+    mut player_query: Query<(&Invulnerable, &mut TextureAtlasSprite), With<Player>>,
+) {
+    player_query
+        .iter_mut()
+        .for_each(|(invulnerable, mut sprite)| {
+            let blink = (invulnerable.0.elapsed_secs() / BLINK_SECS) as u32;
+            let visible = invulnerable.0.finished() || blink % 2 == 1;
+            sprite.color.set_a(if visible { 1. } else { 0.2 });
+        });
+}
+
+type RespawnQuery = (
+    Entity,
+    &'static mut Transform,
+    &'static mut LinearVelocity,
+    &'static mut GravityScale,
+    &'static mut Health,
+    &'static mut Lives,
+    &'static mut PhysicsInterpolation,
+    &'static SpawnPoint,
+    &'static PlayerState,
+    &'static TimeInState,
+);
+
+// Once the player has been dead long enough they come back at the spawn with
+// full health, or the level ends if there are no lives left
+fn respawn_dead_player(
+    constants: Res<Constants>,
+    mut force_state_events: EventWriter<ForcePlayerState>,
+    mut out_of_lives_events: EventWriter<OutOfLives>,
+    mut player_query: Query<RespawnQuery, With<Player>>,
+) {
+    player_query.iter_mut().for_each(
+        |(
+            entity,
+            mut transform,
+            mut velocity,
+            mut gravity_scale,
+            mut health,
+            mut lives,
+            mut interpolation,
+            spawn_point,
+            state,
+            time_in_state,
+        )| {
+            if *state != PlayerState::Dead
+                || time_in_state.0.elapsed_secs() < constants.respawn_time
+                || lives.0 == 0
+            {
+                return;
+            }
+            lives.0 -= 1;
+            if lives.0 == 0 {
+                out_of_lives_events.send(OutOfLives);
+                return;
+            }
+            transform.translation.x = spawn_point.0.x;
+            transform.translation.y = spawn_point.0.y;
+            interpolation.teleport(spawn_point.0);
+            velocity.0 = Vec2::ZERO;
+            gravity_scale.0 = 1.;
+            health.current = health.max;
+            force_state_events.send(ForcePlayerState {
+                entity,
+                state: PlayerState::Idle,
+            });
+        },
+    );
+}
+
+pub struct HealthManager {
+    pub scene: Scene,
+}
+impl Plugin for HealthManager {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OutOfLives>();
+        app.add_systems(
+            FixedUpdate,
+            (
+                // enemies stomped this step are already on their way out
+                damage_player_on_enemy_contact.after(stomp_enemies),
+                respawn_dead_player,
+            )
+                // getting hurt has to reach the state machine in the same step
+                .before(update_player_state)
+                .in_set(GameplaySet)
+                .run_if(in_state(self.scene)),
+        );
+        app.add_systems(
+            Update,
+            blink_invulnerable_player.run_if(in_state(self.scene)),
+        );
+    }
+}
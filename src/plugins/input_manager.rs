@@ -0,0 +1,239 @@
+use bevy::{
+    app::{App, FixedUpdate, Plugin, PreUpdate},
+    ecs::{
+        component::Component,
+        event::EventReader,
+        schedule::IntoSystemConfigs,
+        system::{Query, Res, ResMut, Resource, SystemParam},
+    },
+    input::{
+        gamepad::{
+            Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadConnectionEvent, Gamepads,
+        },
+        keyboard::KeyCode,
+        Axis, Input, InputSystem,
+    },
+    log::info,
+    math::Vec2,
+    prelude::{Deref, DerefMut},
+};
+
+use crate::service::{
+    bindings::{Action, Bindings},
+    constants::Constants,
+};
+
+use super::physics::GameplaySet;
+
+// Where a player's input comes from
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputDevice {
+    // the keyboard and every gamepad, for when there is only one player
+    Any,
+    Keyboard,
+    Gamepad(Gamepad),
+}
+
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+
+// Everyone playing on this machine, in player order
+#[derive(Resource)]
+pub struct LocalPlayers(pub Vec<InputDevice>);
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        Self(vec![InputDevice::Any])
+    }
+}
+
+// A player's actions as the fixed step sees them. A frame can run any number of
+// fixed steps, so presses and releases are held here until a step has seen them
+// instead of only lasting the frame they happened in
+#[derive(Component, Deref, DerefMut, Default)]
+pub struct FixedActions(pub Input<Action>);
+
+// Everything needed to tell whether an action is held on some device
+#[derive(SystemParam)]
+pub struct DeviceInputs<'w> {
+    keyboard_input: Res<'w, Input<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    constants: Res<'w, Constants>,
+    bindings: Res<'w, Bindings>,
+}
+
+impl<'w> DeviceInputs<'w> {
+    fn uses_gamepad(device: InputDevice, gamepad: Gamepad) -> bool {
+        match device {
+            InputDevice::Any => true,
+            InputDevice::Keyboard => false,
+            InputDevice::Gamepad(own_gamepad) => own_gamepad == gamepad,
+        }
+    }
+
+    // Left stick of the first of the device's gamepads that is pushed past the deadzone
+    fn left_stick(&self, device: InputDevice) -> Vec2 {
+        let deadzone = self.constants.gamepad_deadzone;
+        self.gamepads
+            .iter()
+            .filter(|gamepad| Self::uses_gamepad(device, *gamepad))
+            .map(|gamepad| {
+                Vec2::new(
+                    self.gamepad_axes
+                        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+                        .unwrap_or(0.),
+                    self.gamepad_axes
+                        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+                        .unwrap_or(0.),
+                )
+            })
+            .find(|stick| stick.length() > deadzone)
+            .unwrap_or(Vec2::ZERO)
+    }
+
+    pub fn held(&self, action: Action, device: InputDevice) -> bool {
+        let deadzone = self.constants.gamepad_deadzone;
+        let flick = self.constants.gamepad_flick_threshold;
+        let from_keyboard = matches!(device, InputDevice::Any | InputDevice::Keyboard)
+            && self
+                .keyboard_input
+                .any_pressed(self.bindings.keys_for(action));
+        let from_buttons = self
+            .gamepads
+            .iter()
+            .filter(|gamepad| Self::uses_gamepad(device, *gamepad))
+            .any(|gamepad| {
+                self.gamepad_buttons.any_pressed(
+                    self.bindings
+                        .buttons_for(action)
+                        .into_iter()
+                        .map(|button_type| GamepadButton::new(gamepad, button_type)),
+                )
+            });
+        let stick = self.left_stick(device);
+        // pushing the stick all the way is a flick, which counts as a trick
+        // direction the same way the d-pad does
+        let from_stick = match action {
+            Action::MoveLeft => stick.x < -deadzone,
+            Action::MoveRight => stick.x > deadzone,
+            Action::AimUp => stick.y > deadzone,
+            Action::AimDown | Action::Crouch => stick.y < -deadzone,
+            Action::TrickLeft => stick.x < -flick,
+            Action::TrickRight => stick.x > flick,
+            Action::TrickUp | Action::MenuUp => stick.y > flick,
+            Action::TrickDown | Action::MenuDown => stick.y < -flick,
+            _ => false,
+        };
+        from_keyboard || from_buttons || from_stick
+    }
+
+    // Gamepads that just pressed one of the action's buttons
+    pub fn gamepads_just_pressed(&self, action: Action) -> Vec<Gamepad> {
+        let buttons = self.bindings.buttons_for(action);
+        self.gamepads
+            .iter()
+            .filter(|gamepad| {
+                buttons.iter().any(|button_type| {
+                    self.gamepad_buttons
+                        .just_pressed(GamepadButton::new(*gamepad, *button_type))
+                })
+            })
+            .collect()
+    }
+}
+
+// Turns the raw keyboard and gamepad state into actions through the players
+// bindings, runs right after bevy has updated its own input so the rest of
+// the frame can read Input<Action> like any other input. Every device counts
+// here, menus don't care who pressed what.
+// Gamepads are looked up every frame so one plugged in mid level just works
+fn update_actions(device_inputs: DeviceInputs, mut actions: ResMut<Input<Action>>) {
+    actions.clear();
+    Action::ALL.iter().for_each(|action| {
+        if device_inputs.held(*action, InputDevice::Any) {
+            actions.press(*action);
+        } else {
+            actions.release(*action);
+        }
+    });
+}
+
+// Each player only gets the actions from their own device
+fn update_fixed_actions(
+    device_inputs: DeviceInputs,
+    mut player_query: Query<(&InputDevice, &mut FixedActions)>,
+) {
+    player_query
+        .iter_mut()
+        .for_each(|(device, mut fixed_actions)| {
+            Action::ALL.iter().for_each(|action| {
+                if device_inputs.held(*action, *device) {
+                    fixed_actions.press(*action);
+                } else {
+                    fixed_actions.release(*action);
+                }
+            });
+        });
+}
+
+// The gamepad the first player last played with while they were on every
+// device. It's already theirs, so it can't be used to join as someone else
+#[derive(Resource, Default)]
+pub struct AnyDeviceGamepad(pub Option<Gamepad>);
+
+fn track_any_device_gamepad(
+    device_inputs: DeviceInputs,
+    local_players: Res<LocalPlayers>,
+    mut any_device_gamepad: ResMut<AnyDeviceGamepad>,
+) {
+    if local_players.0.first() != Some(&InputDevice::Any) {
+        return;
+    }
+    let played_on = device_inputs.gamepads.iter().find(|gamepad| {
+        Action::ALL
+            .iter()
+            .filter(|action| **action != Action::Join)
+            .any(|action| device_inputs.held(*action, InputDevice::Gamepad(*gamepad)))
+    });
+    if played_on.is_some() {
+        any_device_gamepad.0 = played_on;
+    }
+}
+
+// A fixed step has seen this step's presses and releases
+pub fn clear_fixed_actions(mut player_query: Query<&mut FixedActions>) {
+    player_query
+        .iter_mut()
+        .for_each(|mut fixed_actions| fixed_actions.clear());
+}
+
+fn log_gamepad_connections(mut connection_events: EventReader<GamepadConnectionEvent>) {
+    connection_events.read().for_each(|event| {
+        if event.connected() {
+            info!("gamepad {} connected", event.gamepad.id);
+        } else {
+            info!("gamepad {} disconnected", event.gamepad.id);
+        }
+    });
+}
+
+pub struct InputManager;
+impl Plugin for InputManager {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Input::<Action>::default());
+        app.init_resource::<LocalPlayers>();
+        app.init_resource::<AnyDeviceGamepad>();
+        app.add_systems(
+            PreUpdate,
+            (
+                log_gamepad_connections,
+                update_actions,
+                update_fixed_actions,
+                track_any_device_gamepad,
+            )
+                .chain()
+                .after(InputSystem),
+        );
+        app.add_systems(FixedUpdate, clear_fixed_actions.after(GameplaySet));
+    }
+}
@@ -0,0 +1,220 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        query::With,
+        system::{Query, Res},
+    },
+    math::Vec2,
+    time::{Time, Timer},
+    transform::components::Transform,
+};
+use bevy_xpbd_2d::{
+    components::{Collider, GravityScale, LinearVelocity},
+    plugins::spatial_query::{SpatialQuery, SpatialQueryFilter},
+};
+
+use crate::{
+    components_resources::{Player, TouchingWall},
+    service::{bindings::Action, constants::Constants},
+};
+
+use super::{
+    input_manager::FixedActions,
+    physics::{Layers, PhysicsInterpolation},
+    player_manager::finished_timer,
+    player_state::PlayerState,
+};
+
+// Room left between the player and the ledge so the climb doesn't scrape it
+const CLIMB_CLEARANCE: f32 = 1.;
+
+// The corner of a platform the player is hanging from or climbing onto
+#[derive(Clone, Copy, Debug)]
+struct Ledge {
+    corner: Vec2,
+    // 1 when the platform is to the right of the player, -1 when to the left
+    side: f32,
+}
+
+#[derive(Component)]
+pub struct LedgeGrab {
+    ledge: Option<Ledge>,
+    // runs while climbing up onto the ledge
+    climb: Timer,
+    // runs after letting go so the same ledge isn't grabbed straight away
+    regrab: Timer,
+}
+impl LedgeGrab {
+    pub fn new(constants: &Constants) -> Self {
+        Self {
+            ledge: None,
+            climb: finished_timer(constants.ledge_climb_time),
+            regrab: finished_timer(constants.ledge_regrab_time),
+        }
+    }
+    pub fn is_hanging(&self) -> bool {
+        self.ledge.is_some() && self.climb.finished()
+    }
+    pub fn is_climbing(&self) -> bool {
+        self.ledge.is_some() && !self.climb.finished()
+    }
+    fn let_go(&mut self) {
+        self.ledge = None;
+        self.regrab.reset();
+    }
+}
+
+// A ledge is a wall in front of the player's upper body with nothing above it.
+// Finds the top corner of that wall when there is one
+fn find_ledge(
+    spatial_query: &SpatialQuery,
+    constants: &Constants,
+    position: Vec2,
+    half_extents: Vec2,
+    side: f32,
+) -> Option<Ledge> {
+    let filter = || SpatialQueryFilter::new().with_masks([Layers::Ground]);
+    let reach = half_extents.x + constants.ledge_grab_reach;
+    let direction = Vec2::new(side, 0.);
+    // something to hold on to in front of the chest
+    let chest = position + Vec2::new(0., half_extents.y / 2.);
+    let wall = spatial_query.cast_ray(chest, direction, reach, true, filter())?;
+    // and open air above the head
+    let above_head = position + Vec2::new(0., half_extents.y + constants.ledge_grab_reach);
+    if spatial_query
+        .cast_ray(above_head, direction, reach, true, filter())
+        .is_some()
+    {
+        return None;
+    }
+    // drop down just inside the wall to find its top
+    let inside_wall = Vec2::new(
+        chest.x + side * (wall.time_of_impact + CLIMB_CLEARANCE),
+        above_head.y,
+    );
+    let top = spatial_query.cast_ray(
+        inside_wall,
+        Vec2::NEG_Y,
+        above_head.y - chest.y,
+        true,
+        filter(),
+    )?;
+    Some(Ledge {
+        corner: Vec2::new(
+            chest.x + side * wall.time_of_impact,
+            inside_wall.y - top.time_of_impact,
+        ),
+        side,
+    })
+}
+
+// Everything grabbing, hanging off of and climbing a ledge reads or moves
+type LedgeGrabQuery = (
+    &'static mut LedgeGrab,
+    &'static mut Transform,
+    &'static mut LinearVelocity,
+    &'static mut GravityScale,
+    &'static mut PhysicsInterpolation,
+    &'static Collider,
+    &'static TouchingWall,
+    &'static PlayerState,
+    &'static FixedActions,
+);
+
+// Catches the player on ledges while they fall past them, then hangs, climbs
+// up or lets go depending on input. Runs before the state machine, which
+// reads whether the player is hanging or climbing off of LedgeGrab
+pub fn update_ledge_grab(
+    time: Res<Time>,
+    constants: Res<Constants>,
+    spatial_query: SpatialQuery,
+    mut player_query: Query<LedgeGrabQuery, With<Player>>,
+) {
+    player_query.iter_mut().for_each(
+        |(
+            mut ledge_grab,
+            mut transform,
+            mut velocity,
+            mut gravity_scale,
+            mut interpolation,
+            collider,
+            touching_wall,
+            state,
+            actions,
+        )| {
+            ledge_grab.regrab.tick(time.delta());
+            let half_extents = collider.shape().as_cuboid().unwrap().half_extents;
+            let half_extents = Vec2::new(half_extents[0], half_extents[1]);
+            let Some(ledge) = ledge_grab.ledge else {
+                let side = if touching_wall.right {
+                    1.
+                } else if touching_wall.left {
+                    -1.
+                } else {
+                    return;
+                };
+                if !matches!(state, PlayerState::Fall | PlayerState::WallSlide)
+                    || !ledge_grab.regrab.finished()
+                {
+                    return;
+                }
+                let position = transform.translation.truncate();
+                let Some(ledge) =
+                    find_ledge(&spatial_query, &constants, position, half_extents, side)
+                else {
+                    return;
+                };
+                // hang with the top of the player level with the ledge
+                transform.translation.y = ledge.corner.y - half_extents.y;
+                interpolation.teleport(transform.translation.truncate());
+                velocity.0 = Vec2::ZERO;
+                gravity_scale.0 = 0.;
+                ledge_grab.ledge = Some(ledge);
+                return;
+            };
+            if !state.has_control() {
+                ledge_grab.let_go();
+                gravity_scale.0 = 1.;
+                return;
+            }
+            if ledge_grab.is_climbing() {
+                ledge_grab.climb.tick(time.delta());
+                if ledge_grab.climb.finished() {
+                    ledge_grab.ledge = None;
+                    gravity_scale.0 = 1.;
+                    velocity.0 = Vec2::ZERO;
+                    return;
+                }
+                // straight up until the feet clear the ledge, then over onto it
+                let half_time = constants.ledge_climb_time / 2.;
+                let feet = transform.translation.y - half_extents.y;
+                velocity.0 = if feet < ledge.corner.y + CLIMB_CLEARANCE {
+                    Vec2::new(0., (half_extents.y * 2. + CLIMB_CLEARANCE) / half_time)
+                } else {
+                    Vec2::new(ledge.side * (half_extents.x * 2.) / half_time, 0.)
+                };
+                return;
+            }
+            velocity.0 = Vec2::ZERO;
+            let toward = if ledge.side > 0. {
+                Action::MoveRight
+            } else {
+                Action::MoveLeft
+            };
+            let away = if ledge.side > 0. {
+                Action::MoveLeft
+            } else {
+                Action::MoveRight
+            };
+            if actions.just_pressed(Action::Jump)
+                || actions.pressed(Action::AimUp)
+                || actions.just_pressed(toward)
+            {
+                ledge_grab.climb.reset();
+            } else if actions.pressed(Action::Crouch) || actions.pressed(away) {
+                ledge_grab.let_go();
+                gravity_scale.0 = 1.;
+            }
+        },
+    );
+}
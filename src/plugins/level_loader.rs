@@ -17,17 +17,35 @@ use tiled::{Loader, PropertyValue};
 
 use crate::{
     components_resources::{
-        Checkpoint, CheckpointResource, Collision, Enemy, Object, ObjectComponent, Platform, Point,
-        Size, TextureAtlasHandle, TiledMap, Tileset, TilesetName,
+        Checkpoint, CheckpointResource, CollectedThisRun, Collectible, Collision, Enemy, Object,
+        ObjectComponent, Platform, Point, Size, TextureAtlasHandle, TiledMap, Tileset,
+        TilesetName,
     },
     models::BelongsToScene,
-    plugins::physics::Layers,
+    plugins::physics::{Layers, PhysicsInterpolation},
     scenes::{level::LevelID, Scene},
     service::constants::Constants,
 };
 
 use super::player_manager::initialize_player;
 
+// Builds the texture atlas for a tiled tileset, the image path tiled gives us
+// is absolute so we cut it down to one relative to the assets folder
+pub fn texture_atlas_for(tileset: &tiled::Tileset, asset_server: &AssetServer) -> TextureAtlas {
+    let p = tileset.image.as_ref().unwrap().source.to_str().unwrap();
+    let x = String::from(p);
+    let x = x.split("assets").collect::<Vec<&str>>()[1];
+    let x = format!(".{}", x);
+    TextureAtlas::from_grid(
+        asset_server.load(x),
+        Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
+        tileset.columns as usize,
+        tileset.tilecount as usize / tileset.columns as usize,
+        Some(Vec2::new(tileset.offset_x as f32, tileset.offset_y as f32)),
+        Some(Vec2::new(0., -0.5)),
+    )
+}
+
 pub fn initialize_tiled_map(
     mut commands: Commands,
     constants: Res<Constants>,
@@ -40,19 +58,7 @@ pub fn initialize_tiled_map(
     let map = loader.load_tmx_map(file_path).unwrap();
     let tilesets = map.tilesets();
     for tileset in tilesets {
-        let p = tileset.image.as_ref().unwrap().source.to_str().unwrap();
-        let x = String::from(p);
-        let x = x.split("assets").collect::<Vec<&str>>()[1];
-        let x = format!(".{}", x);
-        let atlas = TextureAtlas::from_grid(
-            asset_server.load(x),
-            Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
-            tileset.columns as usize,
-            tileset.tilecount as usize / tileset.columns as usize,
-            Some(Vec2::new(tileset.offset_x as f32, tileset.offset_y as f32)),
-            Some(Vec2::new(0., -0.5)),
-        );
-        let handle = asset_server.add(atlas);
+        let handle = asset_server.add(texture_atlas_for(tileset, &asset_server));
         commands.spawn((
             BelongsToScene(scene.clone()),
             TilesetName(tileset.name.clone()),
@@ -61,22 +67,7 @@ pub fn initialize_tiled_map(
         commands.insert_resource(TextureAtlasHandle(handle))
     }
     let characters = loader.load_tsx_tileset("./assets/characters.tsx").unwrap();
-    let p = characters.image.as_ref().unwrap().source.to_str().unwrap();
-    let x = String::from(p);
-    let x = x.split("assets").collect::<Vec<&str>>()[1];
-    let x = format!(".{}", x);
-    let atlas = TextureAtlas::from_grid(
-        asset_server.load(x),
-        Vec2::new(characters.tile_width as f32, characters.tile_height as f32),
-        characters.columns as usize,
-        characters.tilecount as usize / characters.columns as usize,
-        Some(Vec2::new(
-            characters.offset_x as f32,
-            characters.offset_y as f32,
-        )),
-        Some(Vec2::new(0., -0.5)),
-    );
-    let handle = asset_server.add(atlas);
+    let handle = asset_server.add(texture_atlas_for(&characters, &asset_server));
     commands.spawn((
         BelongsToScene(scene.clone()),
         TilesetName(characters.name.clone()),
@@ -86,7 +77,7 @@ pub fn initialize_tiled_map(
     commands.insert_resource(TiledMap(map));
 }
 
-fn initialize_checkmarks(mut commands: Commands, map: Res<TiledMap>, scene: Res<State<Scene>>) {
+pub fn initialize_checkmarks(mut commands: Commands, map: Res<TiledMap>, scene: Res<State<Scene>>) {
     map.0.layers().for_each(|layer| {
         if let Some(object_layer) = layer.as_object_layer() {
             object_layer.objects().for_each(|object| {
@@ -173,6 +164,7 @@ fn initialize_enemy_spawns(
                                 commands.spawn((
                                     BelongsToScene(scene.clone()),
                                     Enemy,
+                                    PhysicsInterpolation::new(Vec2::new(object.x, -object.y)),
                                     Collider::cuboid(
                                         character_atlas.2 .0.tile_width as f32,
                                         character_atlas.2 .0.tile_height as f32,
@@ -216,7 +208,45 @@ fn initialize_enemy_spawns(
     });
 }
 
-fn initialize_map_collisions(
+fn initialize_collectibles(mut commands: Commands, map: Res<TiledMap>, scene: Res<State<Scene>>) {
+    commands.insert_resource(CollectedThisRun(vec![]));
+    map.0.layers().for_each(|layer| {
+        if let Some(object_layer) = layer.as_object_layer() {
+            object_layer
+                .objects()
+                .filter(|object| object.properties.contains_key("collectible"))
+                .for_each(|object| {
+                    let size = match object.shape {
+                        tiled::ObjectShape::Rect { width, height } if width > 0. && height > 0. => {
+                            (width, height)
+                        }
+                        _ => (map.0.tile_width as f32, map.0.tile_height as f32),
+                    };
+                    commands.spawn((
+                        BelongsToScene(*scene.get()),
+                        Collectible(object.id()),
+                        Size {
+                            width: size.0,
+                            height: size.1,
+                        },
+                        SpriteBundle {
+                            transform: Transform::from_translation(Vec3::new(
+                                object.x, -object.y, 0.,
+                            )),
+                            sprite: Sprite {
+                                color: Color::GOLD,
+                                custom_size: Some(Vec2::new(size.0 / 2., size.1 / 2.)),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                    ));
+                });
+        }
+    });
+}
+
+pub fn initialize_map_collisions(
     mut commands: Commands,
     map: Res<TiledMap>,
     texture_atlas: Res<TextureAtlasHandle>,
@@ -339,6 +369,7 @@ impl Plugin for LevelLoader {
                     initialize_checkmarks,
                     initialize_map_collisions,
                     initialize_enemy_spawns,
+                    initialize_collectibles,
                     // @TODO this should be in player but needs to be ran after tiled map is initialized
                     initialize_player,
                 ),
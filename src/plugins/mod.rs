@@ -1,8 +1,19 @@
 pub mod animation_manager;
 pub mod camera_controls;
 pub mod config_loader;
+pub mod crouch;
+pub mod dash;
 pub mod delete_manager;
+pub mod ghost;
+pub mod health;
+pub mod input_manager;
+pub mod ledge_grab;
 pub mod level_loader;
 pub mod physics;
 pub mod player_manager;
+pub mod player_state;
+pub mod replay;
+pub mod stomp;
+#[cfg(test)]
+pub mod test_level;
 pub mod trick_manager;
@@ -1,16 +1,27 @@
 use bevy::{
-    app::{App, Plugin, PreUpdate},
+    app::{App, FixedUpdate, Plugin, Update},
     ecs::{
-        schedule::ScheduleLabel,
-        system::{Commands, Res},
+        component::Component,
+        schedule::{IntoSystemConfigs, IntoSystemSetConfigs, ScheduleLabel, SystemSet},
+        system::{Commands, Query, Res},
     },
     math::Vec2,
-    time::{Timer, TimerMode},
+    time::{Fixed, Time},
+    transform::components::Transform,
     utils::intern::Interned,
 };
-use bevy_xpbd_2d::{plugins::PhysicsPlugins, prelude::PhysicsLayer, resources::Gravity};
+use bevy_xpbd_2d::{
+    plugins::{setup::Physics, PhysicsPlugins},
+    prelude::PhysicsLayer,
+    resources::Gravity,
+    PhysicsSet,
+};
+
+use crate::service::constants::Constants;
 
-use crate::{components_resources::LastJumpTime, service::constants::Constants};
+// How many times a second gameplay and physics step, the same on every machine
+// no matter the frame rate
+pub const FIXED_HZ: f64 = 64.;
 
 #[derive(PhysicsLayer)]
 pub enum Layers {
@@ -20,20 +31,91 @@ pub enum Layers {
     Ground,
 }
 
+// Everything that moves the player or reacts to what they touch runs in here,
+// once per fixed step and right before physics steps
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GameplaySet;
+
+// Where a physics body was at the end of the last two fixed steps. Frames land
+// in between steps, so the body is drawn part of the way from one to the other
+#[derive(Component)]
+pub struct PhysicsInterpolation {
+    previous: Vec2,
+    current: Vec2,
+}
+impl PhysicsInterpolation {
+    pub fn new(position: Vec2) -> Self {
+        Self {
+            previous: position,
+            current: position,
+        }
+    }
+
+    // Jumps straight to the new position instead of sliding over to it
+    pub fn teleport(&mut self, position: Vec2) {
+        self.previous = position;
+        self.current = position;
+    }
+}
+
 fn initialize_physics(mut commands: Commands, constants: Res<Constants>) {
-    commands.insert_resource(LastJumpTime(Timer::from_seconds(
-        constants.initial_jump_time,
-        TimerMode::Once,
-    )));
     commands.insert_resource(Gravity(Vec2::NEG_Y * constants.gravity));
 }
 
+// Puts bodies back where physics left them before the step, physics would
+// otherwise take the drawn position as the body being moved
+fn restore_physics_transforms(mut body_query: Query<(&mut Transform, &PhysicsInterpolation)>) {
+    body_query
+        .iter_mut()
+        .for_each(|(mut transform, interpolation)| {
+            transform.translation.x = interpolation.current.x;
+            transform.translation.y = interpolation.current.y;
+        });
+}
+
+fn store_physics_positions(mut body_query: Query<(&Transform, &mut PhysicsInterpolation)>) {
+    body_query
+        .iter_mut()
+        .for_each(|(transform, mut interpolation)| {
+            interpolation.previous = interpolation.current;
+            interpolation.current = transform.translation.truncate();
+        });
+}
+
+pub fn interpolate_physics_transforms(
+    fixed_time: Res<Time<Fixed>>,
+    mut body_query: Query<(&mut Transform, &PhysicsInterpolation)>,
+) {
+    let between_steps = fixed_time.overstep_percentage();
+    body_query
+        .iter_mut()
+        .for_each(|(mut transform, interpolation)| {
+            let position = interpolation
+                .previous
+                .lerp(interpolation.current, between_steps);
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        });
+}
+
 pub struct PhysicsManager {
     pub startup: Interned<dyn ScheduleLabel>,
 }
 impl Plugin for PhysicsManager {
     fn build(&self, app: &mut App) {
-        app.add_plugins(PhysicsPlugins::new(PreUpdate));
+        app.add_plugins(PhysicsPlugins::new(FixedUpdate));
+        // physics steps exactly once for each fixed step
+        app.insert_resource(Time::<Fixed>::from_hz(FIXED_HZ));
+        app.insert_resource(Time::new_with(Physics::fixed_once_hz(FIXED_HZ)));
+        app.configure_sets(FixedUpdate, GameplaySet.before(PhysicsSet::Prepare));
+        app.add_systems(
+            FixedUpdate,
+            (
+                restore_physics_transforms.before(GameplaySet),
+                store_physics_positions.after(PhysicsSet::Sync),
+            ),
+        );
+        app.add_systems(Update, interpolate_physics_transforms);
         app.add_systems(self.startup, initialize_physics);
     }
 }
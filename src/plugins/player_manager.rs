@@ -1,50 +1,205 @@
-use std::{ops::Add, path::Display};
-
 use bevy::{
-    app::{App, Plugin, PostStartup, Update},
+    app::{App, FixedUpdate, Plugin, Update},
     ecs::{
-        query::{With, Without},
-        schedule::{common_conditions::in_state, IntoSystemConfigs, State},
-        system::{Commands, Query, Res, ResMut},
+        entity::Entity,
+        query::With,
+        schedule::{
+            common_conditions::{in_state, not, resource_exists},
+            IntoSystemConfigs, State,
+        },
+        system::{Commands, Query, Res, ResMut, SystemParam},
     },
-    input::{keyboard::KeyCode, Input},
-    math::{Quat, Vec2, Vec3},
-    sprite::{SpriteBundle, SpriteSheetBundle, TextureAtlasSprite},
-    time::Time,
-    transform::components::Transform,
+    hierarchy::BuildChildren,
+    input::gamepad::Gamepad,
+    math::Vec2,
+    render::color::Color,
+    sprite::{SpriteSheetBundle, TextureAtlasSprite},
+    time::{Stopwatch, Time, Timer, TimerMode},
+    transform::{components::Transform, TransformBundle},
 };
 use bevy_xpbd_2d::{
     components::{
-        Collider, CollisionLayers, Friction, LinearVelocity, LockedAxes, Restitution, RigidBody,
+        Collider, CollisionLayers, GravityScale, LinearVelocity, LockedAxes, Restitution,
+        RigidBody, SleepingDisabled,
+    },
+    plugins::{
+        collision::Collisions,
+        spatial_query::{RayCaster, SpatialQueryFilter},
     },
-    math::Scalar,
-    parry::{either::Either::Left, na::RealField},
-    plugins::spatial_query::{RayCaster, RayHits, ShapeCaster, ShapeHits, SpatialQueryFilter},
 };
 use tiled::PropertyValue;
 
 use crate::{
     components_resources::{
-        BottomOfPlayerRayCast, CheckpointCheck, GroundedCheck, LastJumpTime, LastKeyPressed,
-        LeftSideOfPlayerCast, Player, RightSideOfPlayerCast, Score, SquishCheck,
-        TextureAtlasHandle, TiledMap, Tileset, TilesetName,
+        CheckpointCheck, Enemy, GroundNormal, Grounded, JumpWindows, LastJumpTime, Player,
+        PlayerNumber, Score, SelectedCharacter, TextureAtlasHandle, TiledMap, Tileset,
+        TilesetName, TouchingWall, WallJumpLock,
     },
-    models::BelongsToScene,
+    models::{BelongsToScene, CharacterListResource, StatModifiers},
     scenes::Scene,
-    service::constants::Constants,
+    service::{bindings::Action, constants::Constants},
 };
 
 use super::{
-    animation_manager::SpriteAnimationController, delete_manager::DeleteMe, physics::Layers,
+    animation_manager::SpriteAnimationController,
+    crouch::{check_headroom, resize_crouch_collider, Crouch},
+    dash::{fade_dash_trail, spawn_dash_trail, update_dash, Dash},
+    health::{Health, Invulnerable, Lives, SpawnPoint},
+    input_manager::{
+        AnyDeviceGamepad, DeviceInputs, FixedActions, InputDevice, LocalPlayers, MAX_LOCAL_PLAYERS,
+    },
+    ledge_grab::{update_ledge_grab, LedgeGrab},
+    physics::{GameplaySet, Layers, PhysicsInterpolation},
+    player_state::{
+        animate_player_state, flip_player_sprite, update_player_state, Facing, ForcePlayerState,
+        PlayerState, PlayerStateChanged, TimeInState,
+    },
+    replay::ReplayPlayback,
+    stomp::{remember_falling, stomp_enemies, StompCombo},
     trick_manager::Trick,
 };
 
+pub fn finished_timer(secs: f32) -> Timer {
+    let mut timer = Timer::from_seconds(secs, TimerMode::Once);
+    timer.tick(timer.duration());
+    timer
+}
+
+// Tints that tell the local players apart, in player order
+const PLAYER_TINTS: [Color; 4] = [
+    Color::WHITE,
+    Color::rgb(0.6, 0.8, 1.),
+    Color::rgb(1., 0.6, 0.6),
+    Color::rgb(0.6, 1., 0.6),
+];
+
+// Everything every player is spawned with that doesn't change between players
+#[derive(SystemParam)]
+pub struct PlayerSpawner<'w, 's> {
+    constants: Res<'w, Constants>,
+    scene: Res<'w, State<Scene>>,
+    characters: Res<'w, CharacterListResource>,
+    selected_character: Res<'w, SelectedCharacter>,
+    other_atlases: Query<
+        'w,
+        's,
+        (
+            &'static TextureAtlasHandle,
+            &'static TilesetName,
+            &'static Tileset,
+        ),
+    >,
+}
+
+impl<'w, 's> PlayerSpawner<'w, 's> {
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        number: usize,
+        device: InputDevice,
+        spawn_point: Vec2,
+    ) -> Entity {
+        let constants = &self.constants;
+        let scene = self.scene.get();
+        let (char_atlas, _, char_tileset) = self
+            .other_atlases
+            .iter()
+            .find(|(_, name, _)| name.0 == constants.character_sheet)
+            .unwrap();
+        let character = self.characters.0.find(self.selected_character.0.as_deref());
+        let idle = character.animations.for_state(PlayerState::Idle);
+        // players start side by side so they don't spawn inside each other
+        let position =
+            spawn_point + Vec2::new((number as u32 * char_tileset.0.tile_width) as f32, 0.);
+        let mut sprite = TextureAtlasSprite::new(idle.start);
+        sprite.color = PLAYER_TINTS[number % PLAYER_TINTS.len()];
+        // @TODO handle error here
+        commands
+            .spawn((
+                BelongsToScene(*scene),
+                (
+                    Player,
+                    PlayerNumber(number),
+                    device,
+                    FixedActions::default(),
+                ),
+                (
+                    Grounded(false),
+                    GroundNormal(Vec2::Y),
+                    TouchingWall::default(),
+                    WallJumpLock(finished_timer(constants.wall_jump_lock_time)),
+                    Dash::new(constants),
+                    GravityScale(1.),
+                ),
+                (
+                    LastJumpTime(Timer::from_seconds(
+                        constants.initial_jump_time,
+                        TimerMode::Once,
+                    )),
+                    JumpWindows::new(constants.coyote_time, constants.jump_buffer_time),
+                ),
+                (
+                    PlayerState::Idle,
+                    TimeInState(Stopwatch::new()),
+                    Facing::Right,
+                    character.animations.clone(),
+                ),
+                (
+                    Health {
+                        current: constants.player_health,
+                        max: constants.player_health,
+                    },
+                    Lives(constants.player_lives),
+                    Invulnerable(finished_timer(constants.invulnerable_time)),
+                    SpawnPoint(spawn_point),
+                    PhysicsInterpolation::new(position),
+                ),
+                (Trick::new(constants), Score(0), StompCombo::default()),
+                (
+                    RigidBody::Dynamic,
+                    SleepingDisabled,
+                    LockedAxes::ROTATION_LOCKED,
+                    Restitution::ZERO,
+                ),
+                (
+                    Collider::cuboid(character.collider.width, character.collider.height),
+                    character.modifiers,
+                    Crouch::new(
+                        Vec2::new(character.collider.width, character.collider.height),
+                        char_tileset.0.tile_height as f32,
+                    ),
+                    LedgeGrab::new(constants),
+                ),
+                CollisionLayers::new([Layers::Player], [Layers::Ground, Layers::Enemy]),
+                LinearVelocity::ZERO,
+                SpriteSheetBundle {
+                    transform: Transform::from_translation(position.extend(0.)),
+                    sprite,
+                    texture_atlas: char_atlas.0.clone(),
+                    ..Default::default()
+                },
+                SpriteAnimationController::new(idle.start, idle.end, idle.ms_per_frame),
+            ))
+            .with_children(|children| {
+                // casts from the player's own position since it has none of its own
+                children.spawn((
+                    BelongsToScene(*scene),
+                    TransformBundle::default(),
+                    RayCaster::new(Vec2::ZERO, Vec2::NEG_Y).with_query_filter(
+                        SpatialQueryFilter::new().with_masks([Layers::Checkpoint]),
+                    ),
+                    CheckpointCheck,
+                ));
+            })
+            .id()
+    }
+}
+
 pub fn initialize_player(
     mut commands: Commands,
     map: Res<TiledMap>,
-    constants: Res<Constants>,
-    other_atlases: Query<(&TextureAtlasHandle, &TilesetName, &Tileset)>,
-    scene: Res<State<Scene>>,
+    local_players: Res<LocalPlayers>,
+    player_spawner: PlayerSpawner,
 ) {
     let player_spawn = map.0.layers().find_map(|layer| {
         layer
@@ -74,242 +229,282 @@ pub fn initialize_player(
     }
     let player_spawn = player_spawn.unwrap();
 
-    let (char_atlas, _, char_tileset) = other_atlases
+    local_players
+        .0
         .iter()
-        .find(|(_, name, _)| name.0 == constants.character_sheet)
-        .unwrap();
-    // @TODO handle error here
-    commands.spawn((
-        BelongsToScene(scene.clone()),
-        Player,
-        Trick::new(),
-        Score(0),
-        RigidBody::Dynamic,
-        LockedAxes::ROTATION_LOCKED,
-        Restitution::ZERO,
-        Collider::cuboid(
-            char_tileset.0.tile_width as f32,
-            char_tileset.0.tile_height as f32,
-        ),
-        LastKeyPressed((KeyCode::A, 0)),
-        CollisionLayers::new([Layers::Player], [Layers::Ground, Layers::Enemy]),
-        LinearVelocity::ZERO,
-        SpriteSheetBundle {
-            transform: Transform::from_translation(Vec3::new(player_spawn.0, player_spawn.1, 0.)),
-            sprite: TextureAtlasSprite::new(26),
-            texture_atlas: char_atlas.0.clone(),
-            ..Default::default()
-        },
-        SpriteAnimationController::new(26, 29, 100.),
-    ));
-    commands.spawn((
-        BelongsToScene(scene.clone()),
-        ShapeCaster::new(
-            Collider::cuboid(char_tileset.0.tile_width as f32, 5.),
-            Vec2::ZERO,
-            Scalar::default(),
-            Vec2::NEG_Y,
-        )
-        .with_query_filter(SpatialQueryFilter::new().with_masks([Layers::Ground])),
-        GroundedCheck,
-        BottomOfPlayerRayCast,
-    ));
-
-    commands.spawn((
-        BelongsToScene(scene.clone()),
-        RightSideOfPlayerCast,
-        RayCaster::new(Vec2::ZERO, Vec2::X)
-            .with_query_filter(SpatialQueryFilter::new().with_masks([Layers::Ground])),
-    ));
-    commands.spawn((
-        BelongsToScene(scene.clone()),
-        LeftSideOfPlayerCast,
-        RayCaster::new(Vec2::ZERO, Vec2::NEG_X)
-            .with_query_filter(SpatialQueryFilter::new().with_masks([Layers::Ground])),
-    ));
-
-    commands.spawn((
-        BelongsToScene(scene.clone()),
-        RayCaster::new(Vec2::ZERO, Vec2::NEG_Y)
-            .with_query_filter(SpatialQueryFilter::new().with_masks([Layers::Checkpoint])),
-        CheckpointCheck,
-        BottomOfPlayerRayCast,
-    ));
-
-    commands.spawn((
-        BelongsToScene(scene.clone()),
-        RayCaster::new(Vec2::ZERO, Vec2::NEG_Y)
-            .with_query_filter(SpatialQueryFilter::new().with_masks([Layers::Enemy])),
-        SquishCheck,
-        BottomOfPlayerRayCast,
-    ));
-}
-
-fn update_sides_of_player_raycasts(
-    mut left_ray_query: Query<
-        &mut RayCaster,
-        (With<LeftSideOfPlayerCast>, Without<RightSideOfPlayerCast>),
-    >,
-    mut right_ray_query: Query<
-        &mut RayCaster,
-        (With<RightSideOfPlayerCast>, Without<LeftSideOfPlayerCast>),
-    >,
-    player_query: Query<&Transform, With<Player>>,
-) {
-    let player_transform = player_query.iter().next().unwrap();
-    let new_pos = player_transform.translation.truncate();
-    left_ray_query
-        .iter_mut()
-        .for_each(|mut ray| ray.origin = new_pos);
-    right_ray_query
-        .iter_mut()
-        .for_each(|mut ray| ray.origin = new_pos);
+        .enumerate()
+        .for_each(|(number, device)| {
+            player_spawner.spawn(
+                &mut commands,
+                number,
+                *device,
+                Vec2::new(player_spawn.0, player_spawn.1),
+            );
+        });
 }
 
-fn update_bottom_of_player_raycasts(
-    mut ray_query: Query<&mut RayCaster, With<BottomOfPlayerRayCast>>,
-    mut shape_query: Query<&mut ShapeCaster, With<BottomOfPlayerRayCast>>,
-    player_query: Query<&Transform, With<Player>>,
+// Pressing join on a gamepad nobody is using adds another player mid level.
+// The first player stops listening to every device and keeps whichever one
+// they were playing with
+fn join_with_gamepad(
+    mut commands: Commands,
+    device_inputs: DeviceInputs,
+    any_device_gamepad: Res<AnyDeviceGamepad>,
+    mut local_players: ResMut<LocalPlayers>,
+    mut player_query: Query<(&PlayerNumber, &mut InputDevice, &SpawnPoint), With<Player>>,
+    player_spawner: PlayerSpawner,
 ) {
-    let player_transform = player_query.iter().next().unwrap();
-    ray_query.iter_mut().for_each(|mut ray| {
-        ray.origin = player_transform.translation.truncate();
-    });
-    shape_query.iter_mut().for_each(|mut shape| {
-        shape.origin = player_transform
-            .translation
-            .truncate()
-            .add(Vec2::new(0., -16.));
-    });
+    let on_any_device = local_players.0.first() == Some(&InputDevice::Any);
+    let taken = |gamepad: Gamepad| {
+        local_players.0.contains(&InputDevice::Gamepad(gamepad))
+            || (on_any_device && any_device_gamepad.0 == Some(gamepad))
+    };
+    let Some(gamepad) = device_inputs
+        .gamepads_just_pressed(Action::Join)
+        .into_iter()
+        .find(|gamepad| !taken(*gamepad))
+    else {
+        return;
+    };
+    if local_players.0.len() >= MAX_LOCAL_PLAYERS {
+        return;
+    }
+    let Some(spawn_point) = player_query
+        .iter()
+        .next()
+        .map(|(_, _, spawn_point)| spawn_point.0)
+    else {
+        return;
+    };
+    if on_any_device {
+        // the first player keeps the gamepad they were playing with
+        let first_device = any_device_gamepad
+            .0
+            .map(InputDevice::Gamepad)
+            .unwrap_or(InputDevice::Keyboard);
+        local_players.0[0] = first_device;
+        player_query
+            .iter_mut()
+            .filter(|(number, _, _)| number.0 == 0)
+            .for_each(|(_, mut device, _)| *device = first_device);
+    }
+    let device = InputDevice::Gamepad(gamepad);
+    local_players.0.push(device);
+    player_spawner.spawn(
+        &mut commands,
+        local_players.0.len() - 1,
+        device,
+        spawn_point,
+    );
 }
 
-fn if_enemy_directly_below_player_and_falling_kill_enemy(
-    mut commands: Commands,
+// Sorts everything the player is touching into ground and walls by which way
+// the contact normals point. Anything tilted up by no more than
+// max_slope_angle can be stood on, anything steeper is a wall
+fn update_contacts(
     constants: Res<Constants>,
-    object_below_query: Query<(&RayCaster, &RayHits), With<SquishCheck>>,
-    mut player_query: Query<(&Transform, &mut LinearVelocity, &Collider), With<Player>>,
+    collisions: Res<Collisions>,
+    mut player_query: Query<
+        (Entity, &mut Grounded, &mut GroundNormal, &mut TouchingWall),
+        With<Player>,
+    >,
+    enemy_query: Query<(), With<Enemy>>,
 ) {
-    let (player_transform, mut player_lin_vel, player_collider) =
-        player_query.iter_mut().next().unwrap();
-    if let Some((ray, hits)) = object_below_query.iter().next() {
-        hits.iter_sorted()
-            .next()
-            .map(|hit| (hit.entity, ray.origin + ray.direction * hit.time_of_impact))
-            .map(|(entity, point_hit)| {
-                let player_y = player_transform.translation.y
-                    - player_collider.shape().as_cuboid().unwrap().half_extents[1];
-                let difference_between_y = player_y - point_hit.y;
-                if difference_between_y < 1. {
-                    commands.get_entity(entity).map(|mut entity| {
-                        entity.insert(DeleteMe);
-                        player_lin_vel.y += constants.squish_bounce_force;
-                    });
+    let min_ground_y = constants.max_slope_angle.to_radians().cos();
+    player_query.iter_mut().for_each(
+        |(player, mut grounded, mut ground_normal, mut touching_wall)| {
+            let normals = collisions
+                .collisions_with_entity(player)
+                .filter(|contacts| contacts.during_current_frame)
+                .filter_map(|contacts| {
+                    let player_is_first = contacts.entity1 == player;
+                    let other = if player_is_first {
+                        contacts.entity2
+                    } else {
+                        contacts.entity1
+                    };
+                    (!enemy_query.contains(other)).then_some((contacts, player_is_first))
+                })
+                .flat_map(|(contacts, player_is_first)| {
+                    // the player's rotation is locked so its local normal is the
+                    // world normal, flipped to point from what was hit into the player
+                    contacts.manifolds.iter().map(move |manifold| {
+                        if player_is_first {
+                            -manifold.normal1
+                        } else {
+                            -manifold.normal2
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            let ground = normals
+                .iter()
+                .filter(|normal| normal.y >= min_ground_y)
+                .max_by(|a, b| a.y.total_cmp(&b.y));
+            grounded.0 = ground.is_some();
+            ground_normal.0 = ground.copied().unwrap_or(Vec2::Y);
+            let walls = normals
+                .iter()
+                .filter(|normal| normal.y.abs() < min_ground_y);
+            *touching_wall = TouchingWall::default();
+            walls.for_each(|normal| {
+                // the normal points away from the wall
+                if normal.x < 0. {
+                    touching_wall.right = true;
+                } else {
+                    touching_wall.left = true;
                 }
             });
-    }
+        },
+    );
 }
 
-fn update_velocity_with_input(
+// Decides whether the player leaves the ground this frame. Besides jumping
+// while grounded, a jump pressed up to jump_buffer_time before landing or
+// up to coyote_time after walking off a ledge still goes through
+fn update_jump_windows(
     time: Res<Time>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut time_since_last_jump: ResMut<LastJumpTime>,
-    constants: Res<Constants>,
-    mut player_query: Query<(&mut LinearVelocity, &Transform, &Collider), With<Player>>,
-    mut object_below_query: Query<(&mut ShapeCaster, &ShapeHits), With<GroundedCheck>>,
-    mut right_side_query: Query<
-        (&mut RayCaster, &RayHits),
-        (With<RightSideOfPlayerCast>, Without<LeftSideOfPlayerCast>),
-    >,
-    mut left_side_query: Query<
-        (&mut RayCaster, &RayHits),
-        (With<LeftSideOfPlayerCast>, Without<RightSideOfPlayerCast>),
+    mut player_query: Query<
+        (&mut JumpWindows, &FixedActions, &Grounded, &LinearVelocity),
+        With<Player>,
     >,
 ) {
     player_query
         .iter_mut()
-        .next()
-        .map(|(mut velocity, transform, collider)| {
-            let distance_to_closest_ground =
-                object_below_query
-                    .iter_mut()
-                    .next()
-                    .and_then(|(ray, hits)| {
-                        hits.iter().next().map(|hit| {
-                            (transform.translation.y
-                                - collider.shape().as_cuboid().unwrap().half_extents[1])
-                                - (ray.origin + ray.direction * hit.time_of_impact).y
-                        })
-                    });
-            let distance_to_right = right_side_query
-                .iter_mut()
-                .map(|(ray, hits)| {
-                    hits.iter_sorted().next().map(|hit| {
-                        (transform.translation.x
-                            - collider.shape().as_cuboid().unwrap().half_extents[0])
-                            - (ray.origin + ray.direction * hit.time_of_impact).x
-                    })
-                })
-                .next();
-            if let Some(distance_to_right) = distance_to_right {
-                if let Some(distance_to_right) = distance_to_right {
-                    if distance_to_right.abs() < constants.wall_threshold + 32. {
-                        if keyboard_input.pressed(KeyCode::Back) {
-                            velocity.y += (constants.jump_force / 2.) * time.delta_seconds();
-                            velocity.x -= constants.dash_force * time.delta_seconds();
-                        }
-                    }
-                }
+        .for_each(|(mut jump_windows, actions, grounded, velocity)| {
+            if grounded.0 {
+                jump_windows.coyote.reset();
+            } else {
+                jump_windows.coyote.tick(time.delta());
             }
-            let distance_to_left = left_side_query
-                .iter_mut()
-                .map(|(ray, hits)| {
-                    hits.iter_sorted().next().map(|hit| {
-                        (transform.translation.x
-                            + collider.shape().as_cuboid().unwrap().half_extents[0])
-                            - (ray.origin + ray.direction * hit.time_of_impact).x
-                    })
-                })
-                .next();
-            if let Some(distance_to_left) = distance_to_left {
-                if let Some(distance_to_left) = distance_to_left {
-                    if distance_to_left.abs() < constants.wall_threshold + 32. {
-                        if keyboard_input.pressed(KeyCode::Back) {
-                            velocity.y += constants.jump_force * time.delta_seconds();
-                            velocity.x += constants.dash_force * time.delta_seconds();
-                        }
-                    }
-                }
+            if actions.just_pressed(Action::Jump) {
+                jump_windows.buffer.reset();
+            } else {
+                jump_windows.buffer.tick(time.delta());
             }
-            if let Some(distance_to_ground) = distance_to_closest_ground {
-                if distance_to_ground < constants.grounded_threshold {
-                    if keyboard_input.pressed(KeyCode::A) {
-                        velocity.x -= constants.player_speed * time.delta_seconds();
-                        if constants.max_player_speed < velocity.x.abs() {
-                            velocity.x = -constants.max_player_speed;
-                        }
-                    } else if keyboard_input.pressed(KeyCode::D) {
-                        velocity.x += constants.player_speed * time.delta_seconds();
-                        if constants.max_player_speed < velocity.x {
-                            velocity.x = constants.max_player_speed;
-                        }
-                    }
+            // moving up means we already jumped, coyote time is only for falling off things
+            let can_take_off = grounded.0 || (!jump_windows.coyote.finished() && velocity.y <= 0.);
+            let wants_to_jump =
+                !jump_windows.buffer.finished() || (grounded.0 && actions.pressed(Action::Jump));
+            jump_windows.take_off = can_take_off && wants_to_jump;
+            if jump_windows.take_off {
+                jump_windows.close();
+            }
+        });
+}
+
+// Everything that goes into turning a player's input into their velocity
+type MovementQuery = (
+    &'static mut LinearVelocity,
+    &'static mut WallJumpLock,
+    &'static mut LastJumpTime,
+    &'static mut JumpWindows,
+    &'static FixedActions,
+    &'static PlayerState,
+    &'static TouchingWall,
+    &'static GroundNormal,
+    &'static StatModifiers,
+);
+
+fn update_velocity_with_input(
+    time: Res<Time>,
+    constants: Res<Constants>,
+    mut player_query: Query<MovementQuery, With<Player>>,
+) {
+    player_query.iter_mut().for_each(
+        |(
+            mut velocity,
+            mut wall_jump_lock,
+            mut time_since_last_jump,
+            mut jump_windows,
+            actions,
+            state,
+            touching_wall,
+            ground_normal,
+            modifiers,
+        )| {
+            let max_speed = constants.max_player_speed * modifiers.max_speed;
+            wall_jump_lock.0.tick(time.delta());
+            // a dash or a ledge grab sets its own velocity
+            if !state.has_control() || *state == PlayerState::Dash || state.on_ledge() {
+                return;
+            }
+            if *state == PlayerState::WallSlide {
+                velocity.y = velocity.y.max(-constants.wall_slide_speed);
+            }
+            if !state.on_ground()
+                && !jump_windows.take_off
+                && wall_jump_lock.0.finished()
+                && (touching_wall.left || touching_wall.right)
+                && actions.just_pressed(Action::Jump)
+            {
+                // always straight out from the wall, whichever side it is on
+                let away_from_wall = if touching_wall.left { 1. } else { -1. };
+                velocity.x = away_from_wall * constants.wall_jump_force_x;
+                velocity.y = constants.wall_jump_force_y;
+                wall_jump_lock.0.reset();
+                // the press was used up by the wall jump, don't buffer it for landing
+                jump_windows.close();
+            }
+            if state.on_ground() {
+                // run along the ground, up and down slopes rather than into them
+                let along_ground = Vec2::new(ground_normal.0.y, -ground_normal.0.x);
+                let input = if actions.pressed(Action::MoveLeft) {
+                    -1.
+                } else if actions.pressed(Action::MoveRight) {
+                    1.
+                } else {
+                    0.
+                };
+                let ground_speed = velocity.dot(along_ground);
+                if *state == PlayerState::Slide {
+                    // no steering in a slide, it just loses speed
+                    let slowed = (ground_speed.abs()
+                        - constants.slide_friction * time.delta_seconds())
+                    .max(0.);
+                    velocity.0 = along_ground * slowed * ground_speed.signum();
+                } else if input != 0. {
+                    let max_speed = if *state == PlayerState::Crouch {
+                        constants.crouch_speed
+                    } else {
+                        max_speed
+                    };
+                    let speed = (ground_speed
+                        + input
+                            * constants.player_speed
+                            * modifiers.run_speed
+                            * time.delta_seconds())
+                    .clamp(-max_speed, max_speed);
+                    velocity.0 = along_ground * speed;
                 }
-                if keyboard_input.pressed(KeyCode::Back) {
-                    if distance_to_ground <= constants.grounded_threshold {
-                        time_since_last_jump.0.reset();
-                    }
-                    if !time_since_last_jump.0.finished() && velocity.y >= 0. {
-                        time_since_last_jump.0.tick(time.delta());
-                        let force = constants.jump_force
-                            * time_since_last_jump
-                                .0
-                                .percent_left()
-                                .powf(constants.curve_pow);
-                        velocity.y += force * time.delta_seconds();
-                    }
+                // keep from popping off the top of a slope or bouncing off the floor
+                let off_ground = velocity.dot(ground_normal.0);
+                if off_ground > 0. && !jump_windows.take_off {
+                    velocity.0 -= ground_normal.0 * off_ground;
                 }
             }
-        });
+            if jump_windows.take_off {
+                time_since_last_jump.0.reset();
+                // a coyote jump starts while already falling
+                velocity.y = velocity.y.max(0.);
+            }
+            // a buffered jump may already be released by the time we land,
+            // it still gets its first push
+            if (actions.pressed(Action::Jump) || jump_windows.take_off)
+                && !time_since_last_jump.0.finished()
+                && velocity.y >= 0.
+            {
+                time_since_last_jump.0.tick(time.delta());
+                let force = constants.jump_force
+                    * modifiers.jump_force
+                    * time_since_last_jump
+                        .0
+                        .percent_left()
+                        .powf(constants.curve_pow);
+                velocity.y += force * time.delta_seconds();
+            }
+        },
+    );
 }
 
 pub struct PlayerManager {
@@ -317,14 +512,237 @@ pub struct PlayerManager {
 }
 impl Plugin for PlayerManager {
     fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                update_contacts,
+                stomp_enemies,
+                update_jump_windows,
+                check_headroom,
+                update_ledge_grab,
+                update_dash,
+                update_player_state,
+                resize_crouch_collider,
+                update_velocity_with_input,
+                remember_falling,
+            )
+                .chain()
+                .in_set(GameplaySet)
+                .run_if(in_state(self.scene)),
+        );
         app.add_systems(
             Update,
             (
-                update_sides_of_player_raycasts.run_if(in_state(self.scene)),
-                update_bottom_of_player_raycasts.run_if(in_state(self.scene)),
-                if_enemy_directly_below_player_and_falling_kill_enemy.run_if(in_state(self.scene)),
-                update_velocity_with_input.run_if(in_state(self.scene)),
+                // a replay already knows who joins and when
+                join_with_gamepad.run_if(not(resource_exists::<ReplayPlayback>())),
+                (animate_player_state, flip_player_sprite).chain(),
+                (spawn_dash_trail, fade_dash_trail),
+            )
+                .run_if(in_state(self.scene)),
+        );
+        app.add_event::<PlayerStateChanged>();
+        app.add_event::<ForcePlayerState>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{
+        app::{App, FixedUpdate, Update},
+        ecs::{
+            query::With,
+            schedule::IntoSystemConfigs,
+            system::{Query, ResMut, Resource},
+            world::Mut,
+        },
+        math::Vec2,
+        time::TimeUpdateStrategy,
+        transform::components::Transform,
+        MinimalPlugins,
+    };
+    use bevy_xpbd_2d::{components::LinearVelocity, PhysicsSet};
+
+    use crate::{
+        components_resources::{Grounded, JumpWindows, Player},
+        plugins::{
+            input_manager::FixedActions,
+            physics::GameplaySet,
+            test_level::{headless_level, play_script, Script},
+        },
+        service::bindings::Action,
+    };
+
+    use super::update_jump_windows;
+
+    const FRAME_MS: u64 = 10;
+    const WINDOW_SECS: f32 = 0.1;
+
+    // Just enough of the game to run the jump windows, time moves FRAME_MS per update
+    fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            FRAME_MS,
+        )));
+        app.add_systems(Update, update_jump_windows);
+        app.world.spawn((
+            Player,
+            Grounded(true),
+            LinearVelocity::ZERO,
+            FixedActions::default(),
+            JumpWindows::new(WINDOW_SECS, WINDOW_SECS),
+        ));
+        // the first update only starts the clock
+        app.update();
+        app
+    }
+
+    fn set_grounded(app: &mut App, on_ground: bool) {
+        let mut player_query = app.world.query::<(&mut Grounded, &mut LinearVelocity)>();
+        let (mut grounded, mut velocity) = player_query.single_mut(&mut app.world);
+        grounded.0 = on_ground;
+        velocity.0 = if on_ground {
+            Vec2::ZERO
+        } else {
+            Vec2::new(0., -100.)
+        };
+    }
+
+    fn run_frames(app: &mut App, frames: u64) -> bool {
+        let mut took_off = false;
+        (0..frames).for_each(|_| {
+            app.update();
+            took_off |= app
+                .world
+                .query::<&JumpWindows>()
+                .single(&app.world)
+                .take_off;
+            actions(app).clear();
+        });
+        took_off
+    }
+
+    fn actions(app: &mut App) -> Mut<'_, FixedActions> {
+        app.world
+            .query::<&mut FixedActions>()
+            .single_mut(&mut app.world)
+    }
+
+    fn tap_jump(app: &mut App) -> bool {
+        actions(app).press(Action::Jump);
+        let took_off = run_frames(app, 1);
+        actions(app).release(Action::Jump);
+        took_off
+    }
+
+    #[test]
+    fn jump_while_grounded_fires() {
+        let mut app = headless_app();
+        assert!(tap_jump(&mut app));
+    }
+
+    #[test]
+    fn jump_shortly_after_leaving_ground_fires() {
+        let mut app = headless_app();
+        set_grounded(&mut app, false);
+        assert!(!run_frames(&mut app, 60 / FRAME_MS));
+        assert!(tap_jump(&mut app));
+    }
+
+    #[test]
+    fn jump_long_after_leaving_ground_is_ignored() {
+        let mut app = headless_app();
+        set_grounded(&mut app, false);
+        run_frames(&mut app, 150 / FRAME_MS);
+        assert!(!tap_jump(&mut app));
+    }
+
+    #[test]
+    fn coyote_time_only_gives_one_jump() {
+        let mut app = headless_app();
+        set_grounded(&mut app, false);
+        run_frames(&mut app, 30 / FRAME_MS);
+        assert!(tap_jump(&mut app));
+        assert!(!tap_jump(&mut app));
+    }
+
+    #[test]
+    fn jump_shortly_before_landing_fires_on_landing() {
+        let mut app = headless_app();
+        set_grounded(&mut app, false);
+        run_frames(&mut app, 200 / FRAME_MS);
+        assert!(!tap_jump(&mut app));
+        run_frames(&mut app, 50 / FRAME_MS);
+        set_grounded(&mut app, true);
+        assert!(run_frames(&mut app, 1));
+    }
+
+    #[test]
+    fn jump_long_before_landing_is_ignored() {
+        let mut app = headless_app();
+        set_grounded(&mut app, false);
+        run_frames(&mut app, 200 / FRAME_MS);
+        assert!(!tap_jump(&mut app));
+        run_frames(&mut app, 150 / FRAME_MS);
+        set_grounded(&mut app, true);
+        assert!(!run_frames(&mut app, 1));
+    }
+
+    // Positions the player ends each fixed step at
+    #[derive(Resource, Default)]
+    struct Recorded(Vec<Vec2>);
+
+    const STEPS: usize = 192;
+
+    // The same presses on the same steps every run: run right, jump, dash up
+    // and right, let go, then jump once more after landing
+    fn scripted_input(_: usize, step: u64) -> Vec<Action> {
+        match step {
+            0..=9 | 30..=39 | 45..=79 => vec![Action::MoveRight],
+            10..=29 => vec![Action::MoveRight, Action::Jump],
+            40..=44 => vec![Action::MoveRight, Action::AimUp, Action::Dash],
+            150..=159 => vec![Action::Jump],
+            _ => vec![],
+        }
+    }
+
+    fn record_position(
+        mut recorded: ResMut<Recorded>,
+        player_query: Query<&Transform, With<Player>>,
+    ) {
+        recorded
+            .0
+            .push(player_query.single().translation.truncate());
+    }
+
+    // The whole level schedule, stepped at the fixed rate while frames come
+    // in at the given rate
+    fn simulate_at_fps(fps: f64) -> Vec<Vec2> {
+        let mut app = headless_level(fps);
+        app.insert_resource(Script(scripted_input));
+        app.init_resource::<Recorded>();
+        app.add_systems(
+            FixedUpdate,
+            (
+                play_script.before(GameplaySet),
+                record_position.after(PhysicsSet::Sync),
             ),
         );
+        while app.world.resource::<Recorded>().0.len() < STEPS {
+            app.update();
+        }
+        app.world.resource::<Recorded>().0[..STEPS].to_vec()
+    }
+
+    #[test]
+    fn same_input_gives_same_positions_at_any_frame_rate() {
+        let at_60 = simulate_at_fps(60.);
+        // make sure the script actually moved the player around
+        assert!(at_60.last().unwrap().x > 100.);
+        assert!(at_60.iter().any(|position| position.y > 48.));
+        assert_eq!(simulate_at_fps(30.), at_60);
+        assert_eq!(simulate_at_fps(144.), at_60);
     }
 }
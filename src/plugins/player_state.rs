@@ -0,0 +1,471 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{Event, EventReader, EventWriter},
+        query::With,
+        system::{Query, Res},
+    },
+    math::Vec2,
+    sprite::TextureAtlasSprite,
+    time::{Stopwatch, Time},
+};
+use bevy_xpbd_2d::components::LinearVelocity;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components_resources::{Grounded, JumpWindows, Player, TouchingWall, WallJumpLock},
+    models::PlayerAnimations,
+    service::{bindings::Action, constants::Constants},
+};
+
+use super::{
+    animation_manager::{AnimationTimer, LoopingIncrementer},
+    crouch::Crouch,
+    dash::Dash,
+    input_manager::FixedActions,
+    ledge_grab::LedgeGrab,
+    trick_manager::Trick,
+};
+
+// How long the landing squash lasts before the player can idle or run
+const LAND_SECS: f32 = 0.1;
+// How long getting hit takes control away from the player
+const HURT_SECS: f32 = 0.4;
+// Slower than this on the ground counts as standing still
+const RUN_SPEED_THRESHOLD: f32 = 10.;
+
+// What the player is doing right now, movement and animation both key off this
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PlayerState {
+    Idle,
+    Run,
+    Jump,
+    Fall,
+    WallSlide,
+    Land,
+    Trick,
+    Dash,
+    Hurt,
+    Dead,
+    Crouch,
+    Slide,
+    LedgeHang,
+    LedgeClimb,
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Facing {
+    Left,
+    Right,
+}
+
+// How long the player has been in their current state
+#[derive(Component)]
+pub struct TimeInState(pub Stopwatch);
+
+// Sent whenever the player changes state, for anything that wants to react
+// to the player (tricks, audio, camera)
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlayerStateChanged {
+    pub entity: Entity,
+    pub to: PlayerState,
+}
+
+// States that don't follow from movement (getting hurt, dying, respawning) are
+// requested through this so the change still goes through the state machine
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ForcePlayerState {
+    pub entity: Entity,
+    pub state: PlayerState,
+}
+
+// Everything the state machine looks at to pick the next state
+pub struct PlayerSituation {
+    pub grounded: bool,
+    pub pressing_into_wall: bool,
+    pub velocity: Vec2,
+    pub moving: bool,
+    pub taking_off: bool,
+    pub tricking: bool,
+    pub dashing: bool,
+    pub crouching: bool,
+    // fast enough along the ground to start a slide
+    pub fast_enough_to_slide: bool,
+    // fast enough that a slide keeps going
+    pub slide_has_speed: bool,
+    // false while crouched under something too low to stand up in
+    pub room_to_stand: bool,
+    pub hanging: bool,
+    pub climbing: bool,
+}
+
+impl PlayerState {
+    fn is_airborne(&self) -> bool {
+        matches!(
+            self,
+            PlayerState::Jump
+                | PlayerState::Fall
+                | PlayerState::WallSlide
+                | PlayerState::Trick
+                | PlayerState::Dash
+                | PlayerState::LedgeHang
+                | PlayerState::LedgeClimb
+        )
+    }
+
+    // Whether input moves the player at all
+    pub fn has_control(&self) -> bool {
+        !matches!(self, PlayerState::Hurt | PlayerState::Dead)
+    }
+
+    pub fn on_ground(&self) -> bool {
+        matches!(
+            self,
+            PlayerState::Idle
+                | PlayerState::Run
+                | PlayerState::Land
+                | PlayerState::Crouch
+                | PlayerState::Slide
+        )
+    }
+
+    pub fn on_ledge(&self) -> bool {
+        matches!(self, PlayerState::LedgeHang | PlayerState::LedgeClimb)
+    }
+
+    pub fn next(self, situation: &PlayerSituation, secs_in_state: f32) -> PlayerState {
+        match self {
+            PlayerState::Dead => return PlayerState::Dead,
+            PlayerState::Hurt if secs_in_state < HURT_SECS => return PlayerState::Hurt,
+            PlayerState::Land
+                if secs_in_state < LAND_SECS
+                    && situation.grounded
+                    && !situation.taking_off
+                    && !situation.moving =>
+            {
+                return PlayerState::Land
+            }
+            _ => {}
+        }
+        if situation.climbing {
+            return PlayerState::LedgeClimb;
+        }
+        if situation.hanging {
+            return PlayerState::LedgeHang;
+        }
+        if situation.dashing {
+            return PlayerState::Dash;
+        }
+        if situation.taking_off {
+            return PlayerState::Jump;
+        }
+        if situation.grounded {
+            // the ground check still sees the floor for a few frames after take off
+            if self == PlayerState::Jump && situation.velocity.y > 0. {
+                return PlayerState::Jump;
+            }
+            if self.is_airborne() {
+                return PlayerState::Land;
+            }
+            // a slide keeps going until it runs out of speed, crouching or not
+            if self == PlayerState::Slide && situation.slide_has_speed {
+                return PlayerState::Slide;
+            }
+            if situation.crouching || !situation.room_to_stand {
+                if situation.fast_enough_to_slide
+                    && matches!(self, PlayerState::Run | PlayerState::Land)
+                {
+                    return PlayerState::Slide;
+                }
+                return PlayerState::Crouch;
+            }
+            if situation.moving || situation.velocity.x.abs() > RUN_SPEED_THRESHOLD {
+                return PlayerState::Run;
+            }
+            return PlayerState::Idle;
+        }
+        if situation.tricking {
+            PlayerState::Trick
+        } else if situation.pressing_into_wall && situation.velocity.y <= 0. {
+            PlayerState::WallSlide
+        } else if situation.velocity.y > 0. {
+            PlayerState::Jump
+        } else {
+            PlayerState::Fall
+        }
+    }
+}
+
+// Everything the state machine reads off of a player to pick their next state
+type StateMachineQuery = (
+    Entity,
+    &'static mut PlayerState,
+    &'static mut TimeInState,
+    &'static mut Facing,
+    &'static Grounded,
+    &'static TouchingWall,
+    &'static WallJumpLock,
+    &'static LinearVelocity,
+    &'static Trick,
+    &'static Dash,
+    &'static JumpWindows,
+    &'static FixedActions,
+    &'static Crouch,
+    &'static LedgeGrab,
+);
+
+pub fn update_player_state(
+    time: Res<Time>,
+    constants: Res<Constants>,
+    mut force_state_events: EventReader<ForcePlayerState>,
+    mut state_changed_events: EventWriter<PlayerStateChanged>,
+    mut player_query: Query<StateMachineQuery, With<Player>>,
+) {
+    let forced = force_state_events.read().copied().collect::<Vec<_>>();
+    player_query.iter_mut().for_each(
+        |(
+            entity,
+            mut state,
+            mut time_in_state,
+            mut facing,
+            grounded,
+            touching_wall,
+            wall_jump_lock,
+            velocity,
+            trick,
+            dash,
+            jump_windows,
+            actions,
+            crouch,
+            ledge_grab,
+        )| {
+            time_in_state.0.tick(time.delta());
+            let speed = velocity.x.abs();
+            let next_state = forced
+                .iter()
+                .rev()
+                .find(|force| force.entity == entity)
+                .map(|force| force.state)
+                .unwrap_or_else(|| {
+                    state.next(
+                        &PlayerSituation {
+                            grounded: grounded.0,
+                            pressing_into_wall: wall_jump_lock.0.finished()
+                                && ((touching_wall.left && actions.pressed(Action::MoveLeft))
+                                    || (touching_wall.right && actions.pressed(Action::MoveRight))),
+                            velocity: velocity.0,
+                            moving: actions.pressed(Action::MoveLeft)
+                                || actions.pressed(Action::MoveRight),
+                            taking_off: jump_windows.take_off && state.has_control(),
+                            tricking: trick.is_executing(),
+                            dashing: dash.is_dashing(),
+                            crouching: actions.pressed(Action::Crouch),
+                            fast_enough_to_slide: speed >= constants.slide_min_speed,
+                            slide_has_speed: speed > constants.crouch_speed,
+                            room_to_stand: crouch.room_to_stand,
+                            hanging: ledge_grab.is_hanging(),
+                            climbing: ledge_grab.is_climbing(),
+                        },
+                        time_in_state.0.elapsed_secs(),
+                    )
+                });
+            if state.has_control() {
+                if actions.pressed(Action::MoveLeft) {
+                    *facing = Facing::Left;
+                } else if actions.pressed(Action::MoveRight) {
+                    *facing = Facing::Right;
+                }
+            }
+            if next_state != *state {
+                state_changed_events.send(PlayerStateChanged {
+                    entity,
+                    to: next_state,
+                });
+                *state = next_state;
+                time_in_state.0.reset();
+            }
+        },
+    );
+}
+
+// Swaps the looping frames over to the new state's animation
+pub fn animate_player_state(
+    mut state_changed_events: EventReader<PlayerStateChanged>,
+    mut player_query: Query<(
+        &PlayerAnimations,
+        &mut LoopingIncrementer,
+        &mut AnimationTimer,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    state_changed_events.read().for_each(|changed| {
+        if let Ok((animations, mut incrementer, mut timer, mut sprite)) =
+            player_query.get_mut(changed.entity)
+        {
+            let animation = animations.for_state(changed.to);
+            sprite.index = incrementer.restart(animation.start, animation.end);
+            timer.set_ms_per_frame(animation.ms_per_frame);
+        }
+    });
+}
+
+pub fn flip_player_sprite(mut player_query: Query<(&Facing, &mut TextureAtlasSprite)>) {
+    player_query.iter_mut().for_each(|(facing, mut sprite)| {
+        sprite.flip_x = *facing == Facing::Left;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Standing still on open ground
+    fn standing() -> PlayerSituation {
+        PlayerSituation {
+            grounded: true,
+            pressing_into_wall: false,
+            velocity: Vec2::ZERO,
+            moving: false,
+            taking_off: false,
+            tricking: false,
+            dashing: false,
+            crouching: false,
+            fast_enough_to_slide: false,
+            slide_has_speed: false,
+            room_to_stand: true,
+            hanging: false,
+            climbing: false,
+        }
+    }
+
+    fn falling() -> PlayerSituation {
+        PlayerSituation {
+            grounded: false,
+            velocity: Vec2::new(0., -100.),
+            ..standing()
+        }
+    }
+
+    #[test]
+    fn falling_lands_then_idles() {
+        assert_eq!(PlayerState::Fall.next(&falling(), 0.5), PlayerState::Fall);
+        assert_eq!(PlayerState::Fall.next(&standing(), 0.5), PlayerState::Land);
+        assert_eq!(PlayerState::Land.next(&standing(), 0.), PlayerState::Land);
+        assert_eq!(
+            PlayerState::Land.next(&standing(), LAND_SECS),
+            PlayerState::Idle
+        );
+        // moving or jumping cuts the landing short
+        let running = PlayerSituation {
+            moving: true,
+            ..standing()
+        };
+        assert_eq!(PlayerState::Land.next(&running, 0.), PlayerState::Run);
+        let jumping = PlayerSituation {
+            taking_off: true,
+            ..standing()
+        };
+        assert_eq!(PlayerState::Land.next(&jumping, 0.), PlayerState::Jump);
+    }
+
+    #[test]
+    fn wall_slides_only_while_pressing_into_a_wall_on_the_way_down() {
+        let sliding = PlayerSituation {
+            pressing_into_wall: true,
+            ..falling()
+        };
+        assert_eq!(PlayerState::Fall.next(&sliding, 0.), PlayerState::WallSlide);
+        assert_eq!(
+            PlayerState::WallSlide.next(&sliding, 1.),
+            PlayerState::WallSlide
+        );
+        let rising = PlayerSituation {
+            velocity: Vec2::new(0., 100.),
+            ..sliding
+        };
+        assert_eq!(PlayerState::Jump.next(&rising, 0.), PlayerState::Jump);
+        // letting go of the wall, or reaching the floor, ends the slide
+        assert_eq!(
+            PlayerState::WallSlide.next(&falling(), 0.),
+            PlayerState::Fall
+        );
+        let on_floor = PlayerSituation {
+            pressing_into_wall: true,
+            ..standing()
+        };
+        assert_eq!(
+            PlayerState::WallSlide.next(&on_floor, 0.),
+            PlayerState::Land
+        );
+    }
+
+    #[test]
+    fn input_cant_get_out_of_hurt_or_dead() {
+        let inputs = [
+            PlayerSituation {
+                taking_off: true,
+                ..standing()
+            },
+            PlayerSituation {
+                dashing: true,
+                ..falling()
+            },
+            PlayerSituation {
+                tricking: true,
+                ..falling()
+            },
+            PlayerSituation {
+                moving: true,
+                crouching: true,
+                ..standing()
+            },
+            PlayerSituation {
+                hanging: true,
+                ..falling()
+            },
+        ];
+        inputs.iter().for_each(|situation| {
+            assert_eq!(PlayerState::Hurt.next(situation, 0.), PlayerState::Hurt);
+            assert_eq!(PlayerState::Dead.next(situation, 10.), PlayerState::Dead);
+        });
+        // control comes back once the hit is over
+        assert_eq!(
+            PlayerState::Hurt.next(&inputs[0], HURT_SECS),
+            PlayerState::Jump
+        );
+    }
+
+    #[test]
+    fn ledges_are_hung_from_then_climbed() {
+        let hanging = PlayerSituation {
+            hanging: true,
+            velocity: Vec2::ZERO,
+            ..falling()
+        };
+        assert_eq!(PlayerState::Fall.next(&hanging, 0.), PlayerState::LedgeHang);
+        assert_eq!(
+            PlayerState::LedgeHang.next(&hanging, 1.),
+            PlayerState::LedgeHang
+        );
+        let climbing = PlayerSituation {
+            climbing: true,
+            hanging: false,
+            ..hanging
+        };
+        assert_eq!(
+            PlayerState::LedgeHang.next(&climbing, 0.),
+            PlayerState::LedgeClimb
+        );
+        // climbing ends on top of the ledge, which is a landing
+        assert_eq!(
+            PlayerState::LedgeClimb.next(&standing(), 0.),
+            PlayerState::Land
+        );
+        // dropping off the ledge
+        assert_eq!(
+            PlayerState::LedgeHang.next(&falling(), 0.),
+            PlayerState::Fall
+        );
+    }
+}
@@ -0,0 +1,418 @@
+use bevy::{
+    app::{App, FixedUpdate, Plugin},
+    ecs::{
+        query::With,
+        schedule::{
+            common_conditions::{in_state, resource_exists},
+            IntoSystemConfigs, OnEnter, OnExit,
+        },
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    log::{info, warn},
+};
+use bevy_xpbd_2d::PhysicsSet;
+use tiled::PropertyValue;
+
+use crate::{
+    components_resources::{Player, PlayerNumber, SelectedCharacter, TiledMap},
+    scenes::{
+        level::{LevelID, LevelStopwatch},
+        Scene,
+    },
+    service::{
+        constants::Constants,
+        replay::{hash_constants, ActionFrame, InputTrack, Replay},
+        user_stats::LevelResult,
+    },
+};
+
+use super::{
+    health::SpawnPoint,
+    input_manager::{FixedActions, InputDevice, LocalPlayers},
+    physics::GameplaySet,
+    player_manager::{initialize_player, PlayerSpawner},
+};
+
+// How many fixed steps have run since the level was entered
+#[derive(Resource, Default)]
+pub struct ReplayStep(pub u64);
+
+// The run being recorded, every normal run through a level is
+#[derive(Resource)]
+pub struct ReplayRecorder(Replay);
+
+// A run being played back instead of read from the players' devices
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    // who was playing before the replay took over, they get it back once it's done
+    previous_character: Option<String>,
+    previous_players: Vec<InputDevice>,
+}
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            previous_character: None,
+            previous_players: LocalPlayers::default().0,
+        }
+    }
+}
+
+// Generated levels keep the seed they were made from as a map property
+fn map_seed(map: &tiled::Map) -> u64 {
+    match map.properties.get("seed") {
+        Some(PropertyValue::StringValue(seed)) => seed.parse().unwrap_or(0),
+        Some(PropertyValue::IntValue(seed)) => *seed as u64,
+        _ => 0,
+    }
+}
+
+fn start_replay(
+    mut commands: Commands,
+    constants: Res<Constants>,
+    level_id: Res<LevelID>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut selected_character: ResMut<SelectedCharacter>,
+    mut local_players: ResMut<LocalPlayers>,
+) {
+    commands.insert_resource(ReplayStep(0));
+    let Some(mut playback) = playback else {
+        commands.insert_resource(ReplayRecorder(Replay {
+            level_id: level_id.0.clone(),
+            constants_hash: hash_constants(&constants),
+            seed: 0,
+            character: selected_character.0.clone(),
+            players: vec![],
+            result: None,
+        }));
+        return;
+    };
+    if playback.replay.level_id != level_id.0 {
+        warn!("replay was recorded on {}", playback.replay.level_id);
+    }
+    if playback.replay.constants_hash != hash_constants(&constants) {
+        warn!("replay was recorded with different constants, it may not play out the same");
+    }
+    playback.previous_character = selected_character.0.clone();
+    selected_character.0 = playback.replay.character.clone();
+    // players who joined mid level get spawned when their track starts
+    let starting_players = playback
+        .replay
+        .players
+        .iter()
+        .filter(|track| track.start_step == 0)
+        .count()
+        .max(1);
+    playback.previous_players = std::mem::replace(
+        &mut local_players.0,
+        vec![InputDevice::Keyboard; starting_players],
+    );
+}
+
+fn record_inputs(
+    step: Res<ReplayStep>,
+    level_stopwatch: Res<LevelStopwatch>,
+    mut recorder: ResMut<ReplayRecorder>,
+    player_query: Query<(&PlayerNumber, &FixedActions), With<Player>>,
+) {
+    if level_stopwatch.finished() {
+        return;
+    }
+    let mut players = player_query.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(number, _)| number.0);
+    players.iter().for_each(|(number, actions)| {
+        if number.0 >= recorder.0.players.len() {
+            recorder.0.players.push(InputTrack::new(step.0));
+        }
+        recorder.0.players[number.0].push(ActionFrame::from_input(actions));
+    });
+}
+
+// Overwrites whatever the devices said with the recorded input
+fn play_back_inputs(
+    step: Res<ReplayStep>,
+    playback: Res<ReplayPlayback>,
+    mut player_query: Query<(&PlayerNumber, &mut FixedActions), With<Player>>,
+) {
+    player_query.iter_mut().for_each(|(number, mut actions)| {
+        playback
+            .replay
+            .players
+            .get(number.0)
+            .and_then(|track| track.frame_at(step.0))
+            .unwrap_or_default()
+            .apply_to(&mut actions);
+    });
+}
+
+// Players that joined mid level are spawned once the step their track starts
+// on comes up, once physics is done with the step before it, the same as when
+// they joined between frames while it was recorded
+fn spawn_late_joiners(
+    mut commands: Commands,
+    step: Res<ReplayStep>,
+    playback: Res<ReplayPlayback>,
+    mut local_players: ResMut<LocalPlayers>,
+    player_query: Query<(&PlayerNumber, &SpawnPoint), With<Player>>,
+    player_spawner: PlayerSpawner,
+) {
+    let Some(spawn_point) = player_query
+        .iter()
+        .find(|(number, _)| number.0 == 0)
+        .map(|(_, spawn_point)| spawn_point.0)
+    else {
+        return;
+    };
+    playback
+        .replay
+        .players
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| track.start_step > 0 && track.start_step == step.0)
+        .for_each(|(number, _)| {
+            local_players.0.push(InputDevice::Keyboard);
+            player_spawner.spawn(&mut commands, number, InputDevice::Keyboard, spawn_point);
+        });
+}
+
+pub fn advance_replay_step(mut step: ResMut<ReplayStep>) {
+    step.0 += 1;
+}
+
+// Saves the recording, or when playing one back checks it ended the same way
+fn finish_replay(
+    commands: &mut Commands,
+    constants: &Constants,
+    map: &TiledMap,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<Res<ReplayPlayback>>,
+    result: Option<LevelResult>,
+) {
+    if let Some(mut recorder) = recorder {
+        recorder.0.seed = map_seed(&map.0);
+        recorder.0.result = result;
+        let file_path = recorder.0.save(constants);
+        info!("saved replay to {}", file_path);
+        commands.remove_resource::<ReplayRecorder>();
+        return;
+    }
+    let Some(playback) = playback else {
+        return;
+    };
+    let describe = |result: &Option<LevelResult>| {
+        result
+            .as_ref()
+            .map(|result| format!("score {} in {}ms", result.score, result.time))
+            .unwrap_or("no finish".to_string())
+    };
+    let matches = match (&playback.replay.result, &result) {
+        (Some(recorded), Some(replayed)) => {
+            recorded.score == replayed.score && recorded.time == replayed.time
+        }
+        (None, None) => true,
+        _ => false,
+    };
+    if matches {
+        info!("replay matches the recording, {}", describe(&result));
+    } else {
+        warn!(
+            "replay doesn't match the recording, recorded {} but replayed {}",
+            describe(&playback.replay.result),
+            describe(&result)
+        );
+    }
+}
+
+pub fn finish_replay_on_completed(
+    mut commands: Commands,
+    constants: Res<Constants>,
+    map: Res<TiledMap>,
+    level_result: Res<LevelResult>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    finish_replay(
+        &mut commands,
+        &constants,
+        &map,
+        recorder,
+        playback,
+        Some(level_result.clone()),
+    );
+}
+
+pub fn finish_replay_on_failed(
+    mut commands: Commands,
+    constants: Res<Constants>,
+    map: Res<TiledMap>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    finish_replay(&mut commands, &constants, &map, recorder, playback, None);
+}
+
+// Quitting a level throws its recording away, and a replay only plays once
+fn stop_replay(
+    mut commands: Commands,
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut selected_character: ResMut<SelectedCharacter>,
+    mut local_players: ResMut<LocalPlayers>,
+) {
+    commands.remove_resource::<ReplayRecorder>();
+    commands.remove_resource::<ReplayPlayback>();
+    if let Some(mut playback) = playback {
+        selected_character.0 = playback.previous_character.take();
+        local_players.0 = std::mem::take(&mut playback.previous_players);
+    }
+}
+
+pub struct ReplayManager {
+    pub scene: Scene,
+}
+impl Plugin for ReplayManager {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(self.scene), start_replay.before(initialize_player));
+        app.add_systems(
+            FixedUpdate,
+            (
+                (
+                    play_back_inputs.run_if(resource_exists::<ReplayPlayback>()),
+                    record_inputs.run_if(resource_exists::<ReplayRecorder>()),
+                )
+                    .chain()
+                    .before(GameplaySet),
+                (
+                    advance_replay_step,
+                    spawn_late_joiners.run_if(resource_exists::<ReplayPlayback>()),
+                )
+                    .chain()
+                    .after(PhysicsSet::Sync),
+            )
+                .run_if(in_state(self.scene)),
+        );
+        app.add_systems(OnExit(self.scene), stop_replay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        app::{App, FixedUpdate},
+        ecs::{
+            query::With,
+            schedule::{IntoSystemConfigs, NextState},
+        },
+        math::Vec2,
+        transform::components::Transform,
+    };
+
+    use crate::{
+        components_resources::{Player, PlayerNumber, Score, SelectedCharacter},
+        plugins::{
+            input_manager::{InputDevice, LocalPlayers},
+            test_level::{headless_level, play_script, spawn_player, Script},
+        },
+        scenes::Scene,
+        service::{bindings::Action, replay::Replay},
+    };
+
+    use super::{record_inputs, ReplayPlayback, ReplayRecorder, ReplayStep};
+
+    const STEPS: u64 = 150;
+    const JOIN_STEP: u64 = 20;
+
+    // The first player runs, jumps and does an up flip on the way up. The
+    // second joins partway through and heads left from the moment they land,
+    // so a step early or late shows up in where they end up
+    fn scripted_input(player: usize, step: u64) -> Vec<Action> {
+        match (player, step) {
+            (0, 0..=29) => vec![Action::MoveRight],
+            (0, 30..=35 | 37 | 39..=45) => vec![Action::Jump],
+            (0, 36 | 38) => vec![Action::Jump, Action::TrickUp],
+            (1, 20..=44 | 51..=70) => vec![Action::MoveLeft],
+            (1, 45..=50) => vec![Action::MoveLeft, Action::Jump],
+            _ => vec![],
+        }
+    }
+
+    // The step count only shows up once the level's been entered
+    fn run_until(app: &mut App, step: u64) {
+        while app
+            .world
+            .get_resource::<ReplayStep>()
+            .map_or(0, |replay_step| replay_step.0)
+            < step
+        {
+            app.update();
+        }
+    }
+
+    // Where every player ended up and what they scored, by player number
+    fn outcome(app: &mut App) -> Vec<(usize, Vec2, usize)> {
+        let mut players = app
+            .world
+            .query_filtered::<(&PlayerNumber, &Transform, &Score), With<Player>>()
+            .iter(&app.world)
+            .map(|(number, transform, score)| (number.0, transform.translation.truncate(), score.0))
+            .collect::<Vec<_>>();
+        players.sort_by_key(|(number, _, _)| *number);
+        players
+    }
+
+    // Frames come in at the fixed rate so both runs stop on the same step
+    fn record() -> (Replay, Vec<(usize, Vec2, usize)>) {
+        let mut app = headless_level(64.);
+        app.insert_resource(Script(scripted_input));
+        app.add_systems(FixedUpdate, play_script.before(record_inputs));
+        run_until(&mut app, JOIN_STEP);
+        spawn_player(&mut app);
+        run_until(&mut app, STEPS);
+        let replay = app.world.resource::<ReplayRecorder>().0.clone();
+        (replay, outcome(&mut app))
+    }
+
+    fn play_back(replay: Replay) -> Vec<(usize, Vec2, usize)> {
+        let mut app = headless_level(64.);
+        app.insert_resource(ReplayPlayback::new(replay));
+        run_until(&mut app, STEPS);
+        outcome(&mut app)
+    }
+
+    #[test]
+    fn playing_back_a_run_ends_the_same_way() {
+        let (replay, recorded) = record();
+        // make sure the second player joined late and the trick was banked
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(replay.players[1].start_step, JOIN_STEP);
+        assert!(recorded[0].2 > 0);
+        assert!(recorded[1].1.x < -50.);
+        assert_eq!(play_back(replay), recorded);
+    }
+
+    #[test]
+    fn leaving_a_replay_gives_the_players_back() {
+        let (mut replay, _) = record();
+        replay.character = Some("replayed".to_string());
+        let mut app = headless_level(64.);
+        app.insert_resource(SelectedCharacter(Some("picked".to_string())));
+        app.insert_resource(LocalPlayers(vec![InputDevice::Any; 2]));
+        app.insert_resource(ReplayPlayback::new(replay));
+        run_until(&mut app, 1);
+        assert_eq!(
+            app.world.resource::<SelectedCharacter>().0.as_deref(),
+            Some("replayed")
+        );
+        app.insert_resource(NextState(Some(Scene::Home)));
+        app.update();
+        assert!(app.world.get_resource::<ReplayPlayback>().is_none());
+        assert_eq!(
+            app.world.resource::<SelectedCharacter>().0.as_deref(),
+            Some("picked")
+        );
+        assert_eq!(
+            app.world.resource::<LocalPlayers>().0,
+            vec![InputDevice::Any; 2]
+        );
+    }
+}
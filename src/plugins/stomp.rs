@@ -0,0 +1,108 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        system::{Commands, Query, Res},
+    },
+    math::Vec2,
+};
+use bevy_xpbd_2d::{
+    components::LinearVelocity,
+    plugins::collision::{Collisions, Contacts},
+};
+
+use crate::{
+    components_resources::{Enemy, Grounded, Player, Score},
+    service::constants::Constants,
+};
+
+use super::delete_manager::DeleteMe;
+
+// How much of the contact normal has to point down out of the player for the
+// contact to count as landing on top of the enemy
+const STOMP_NORMAL_THRESHOLD: f32 = 0.7;
+
+// Enemies stomped since the player last touched the ground, each one in a row
+// is worth more than the last
+#[derive(Component, Default)]
+pub struct StompCombo {
+    pub count: u32,
+    // whether the player was heading down going into the last physics step,
+    // the solver has already stopped the fall by the time contacts are read
+    was_falling: bool,
+}
+
+// The most downward of the contact normals pointing out of the player
+fn player_normal(contacts: &Contacts, player: Entity) -> Option<Vec2> {
+    let player_is_first = contacts.entity1 == player;
+    // the player's rotation is locked so its local normal is the world normal
+    contacts
+        .manifolds
+        .iter()
+        .map(|manifold| {
+            if player_is_first {
+                manifold.normal1
+            } else {
+                manifold.normal2
+            }
+        })
+        .min_by(|a, b| a.y.total_cmp(&b.y))
+}
+
+impl StompCombo {
+    // Whether the player came down on top of the other entity in the last physics step.
+    // Stomping an enemy and getting hit by one both go by this so they can't disagree
+    pub fn landed_on(&self, collisions: &Collisions, player: Entity, other: Entity) -> bool {
+        self.was_falling
+            && collisions
+                .get(player, other)
+                .and_then(|contacts| player_normal(contacts, player))
+                .is_some_and(|normal| normal.y <= -STOMP_NORMAL_THRESHOLD)
+    }
+}
+
+// Kills every enemy the player landed on in the last physics step, bounces the player once
+// and scores each stomp by how far into the combo it is
+pub fn stomp_enemies(
+    mut commands: Commands,
+    constants: Res<Constants>,
+    collisions: Res<Collisions>,
+    mut player_query: Query<
+        (
+            Entity,
+            &mut LinearVelocity,
+            &mut StompCombo,
+            &mut Score,
+            &Grounded,
+        ),
+        With<Player>,
+    >,
+    enemy_query: Query<Entity, (With<Enemy>, Without<DeleteMe>)>,
+) {
+    player_query
+        .iter_mut()
+        .for_each(|(player, mut velocity, mut combo, mut score, grounded)| {
+            let stomped = enemy_query
+                .iter()
+                .filter(|enemy| combo.landed_on(&collisions, player, *enemy))
+                .collect::<Vec<_>>();
+            if !stomped.is_empty() {
+                stomped.iter().for_each(|enemy| {
+                    combo.count += 1;
+                    score.0 += (constants.stomp_points * combo.count) as usize;
+                    commands.entity(*enemy).insert(DeleteMe);
+                });
+                velocity.y = constants.squish_bounce_force;
+            } else if grounded.0 {
+                combo.count = 0;
+            }
+        });
+}
+
+// Runs once the player's velocity is settled for the step, right before physics
+pub fn remember_falling(mut player_query: Query<(&LinearVelocity, &mut StompCombo), With<Player>>) {
+    player_query.iter_mut().for_each(|(velocity, mut combo)| {
+        combo.was_falling = velocity.y < 0.;
+    });
+}
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use bevy::{
+    app::{App, Startup},
+    ecs::{
+        schedule::{ScheduleLabel, State},
+        system::{Commands, Query, Res, Resource, SystemState},
+    },
+    hierarchy::HierarchyPlugin,
+    input::{Input, InputPlugin},
+    math::Vec2,
+    time::TimeUpdateStrategy,
+    transform::{components::Transform, TransformBundle, TransformPlugin},
+    MinimalPlugins,
+};
+use bevy_xpbd_2d::components::{Collider, CollisionLayers, RigidBody};
+
+use crate::{
+    components_resources::{
+        PlayerNumber, SelectedCharacter, TextureAtlasHandle, Tileset, TilesetName,
+    },
+    models::{CharacterList, CharacterListResource, TrickList},
+    scenes::{
+        level::{LevelID, LevelStopwatch},
+        Scene,
+    },
+    service::{
+        bindings::{Action, Bindings},
+        constants::Constants,
+        replay::ActionFrame,
+        trick_trie::TrickTrie,
+    },
+};
+
+use super::{
+    health::HealthManager,
+    input_manager::{FixedActions, InputDevice, InputManager, LocalPlayers},
+    physics::{Layers, PhysicsManager},
+    player_manager::{PlayerManager, PlayerSpawner},
+    replay::{ReplayManager, ReplayStep},
+    trick_manager::TrickManager,
+};
+
+// Just above the floor
+const SPAWN: Vec2 = Vec2::new(0., 32.);
+
+// The actions a player holds on a fixed step, by player number and step
+#[derive(Resource)]
+pub struct Script(pub fn(usize, u64) -> Vec<Action>);
+
+// A level that's nothing but a long floor, run headless through the same
+// plugins the level scene adds. Frames come in at `fps` while gameplay steps
+// at the fixed rate. The first player is already spawned
+pub fn headless_level(fps: f64) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        HierarchyPlugin,
+        InputPlugin,
+    ));
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        1. / fps,
+    )));
+    let constants = Constants::read_from_file();
+    let trick_list = serde_json::from_str::<TrickList>(
+        &std::fs::read_to_string("./assets/trick_list.json").unwrap(),
+    )
+    .unwrap();
+    let characters = serde_json::from_str::<CharacterList>(
+        &std::fs::read_to_string("./assets/characters.json").unwrap(),
+    )
+    .unwrap();
+    let character_sheet = tiled::Loader::new()
+        .load_tsx_tileset(format!("./assets/{}.tsx", constants.character_sheet))
+        .unwrap();
+    app.world.spawn((
+        TextureAtlasHandle(Default::default()),
+        TilesetName(constants.character_sheet.clone()),
+        Tileset(character_sheet),
+    ));
+    app.world.spawn((
+        RigidBody::Static,
+        Collider::cuboid(100000., 20.),
+        CollisionLayers::new([Layers::Ground], [Layers::Player, Layers::Enemy]),
+        TransformBundle::from_transform(Transform::from_xyz(0., -10., 0.)),
+    ));
+    app.insert_resource(constants);
+    app.insert_resource(Bindings::default());
+    app.insert_resource(TrickTrie::new(&trick_list.tricks));
+    app.insert_resource(CharacterListResource(characters));
+    app.insert_resource(SelectedCharacter(None));
+    app.insert_resource(LevelID("test_level.tmx".to_string()));
+    app.init_resource::<LevelStopwatch>();
+    app.add_state::<Scene>();
+    app.insert_resource(State::new(Scene::Level));
+    app.add_plugins((
+        PhysicsManager {
+            startup: Startup.intern(),
+        },
+        InputManager,
+        PlayerManager {
+            scene: Scene::Level,
+        },
+        TrickManager {
+            scene: Scene::Level,
+        },
+        HealthManager {
+            scene: Scene::Level,
+        },
+        ReplayManager {
+            scene: Scene::Level,
+        },
+    ));
+    app.world.resource_mut::<LocalPlayers>().0 = vec![];
+    spawn_player(&mut app);
+    app
+}
+
+// Joins another player the way a gamepad would, between frames
+pub fn spawn_player(app: &mut App) {
+    let number = app.world.resource::<LocalPlayers>().0.len();
+    app.world
+        .resource_mut::<LocalPlayers>()
+        .0
+        .push(InputDevice::Keyboard);
+    let mut system_state = SystemState::<(Commands, PlayerSpawner)>::new(&mut app.world);
+    let (mut commands, player_spawner) = system_state.get_mut(&mut app.world);
+    player_spawner.spawn(&mut commands, number, InputDevice::Keyboard, SPAWN);
+    system_state.apply(&mut app.world);
+}
+
+// Gives every player exactly the input the script has for this step
+pub fn play_script(
+    step: Res<ReplayStep>,
+    script: Res<Script>,
+    mut player_query: Query<(&PlayerNumber, &mut FixedActions)>,
+) {
+    player_query.iter_mut().for_each(|(number, mut actions)| {
+        let mut input = Input::<Action>::default();
+        if step.0 > 0 {
+            (script.0)(number.0, step.0 - 1)
+                .into_iter()
+                .for_each(|action| input.press(action));
+        }
+        input.clear();
+        let held = (script.0)(number.0, step.0);
+        Action::ALL.iter().for_each(|action| {
+            if held.contains(action) {
+                input.press(*action);
+            } else {
+                input.release(*action);
+            }
+        });
+        ActionFrame::from_input(&input).apply_to(&mut actions);
+    });
+}
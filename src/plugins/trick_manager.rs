@@ -1,132 +1,289 @@
 use std::time::Duration;
 
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, FixedUpdate, Plugin, Update},
     ecs::{
         component::Component,
+        entity::Entity,
+        event::{Event, EventWriter},
         query::With,
         schedule::{common_conditions::in_state, IntoSystemConfigs, ScheduleLabel},
-        system::{Query, Res, ResMut, Resource},
+        system::{Query, Res, Resource, SystemParam},
     },
-    input::{keyboard::KeyCode, Input},
+    math::Quat,
+    sprite::TextureAtlasSprite,
     time::{Time, Timer, TimerMode},
     transform::components::Transform,
     utils::intern::Interned,
 };
-use bevy_xpbd_2d::{
-    components::{Collider, LinearVelocity},
-    plugins::spatial_query::{RayCaster, RayHits, ShapeCaster, ShapeHits},
-};
+use bevy_xpbd_2d::components::{GravityScale, LinearVelocity};
 
 use crate::{
-    components_resources::{GroundedCheck, LastKeyPressed, Player, Score},
-    models::{TrickDefinition, TrickListResource},
+    components_resources::{Grounded, Player, Score},
+    models::{PlayerAnimations, TrickAnimation, TrickDefinition},
     scenes::Scene,
-    service::constants::Constants,
+    service::{
+        bindings::Action,
+        constants::Constants,
+        trick_trie::{TrickMatch, TrickTrie},
+    },
+};
+
+use super::{
+    animation_manager::update_animated_sprites,
+    input_manager::FixedActions,
+    physics::GameplaySet,
+    player_manager::finished_timer,
+    player_state::{animate_player_state, update_player_state, Facing, PlayerState},
 };
 
 #[derive(Component, Resource)]
 pub struct Trick {
     last_trick_definition: Option<TrickDefinition>,
     last_trick_over: Timer,
-    keys: Vec<KeyCode>,
+    keys: Vec<Action>,
+    // runs from the last trick key, the keys are done once it finishes
+    key_window: Timer,
+    // tricks landed since the combo started, in order
+    combo: Vec<TrickDefinition>,
+    // runs while the player is on the ground, the combo is banked once it
+    // finishes so a quick hop keeps it going
+    combo_window: Timer,
+    // the trick started this step and its effects haven't been applied yet
+    just_started: bool,
+    // gravity is off for the trick's hang time
+    hanging: bool,
 }
 impl Trick {
-    pub fn new() -> Self {
+    pub fn new(constants: &Constants) -> Self {
         Self {
             keys: vec![],
             last_trick_definition: None,
             last_trick_over: Timer::from_seconds(0.5, TimerMode::Once),
+            key_window: finished_timer(constants.trick_time / 1000.),
+            combo: vec![],
+            combo_window: finished_timer(constants.combo_land_time),
+            just_started: false,
+            hanging: false,
         }
     }
-    pub fn add_key(&mut self, key: KeyCode) {
+    pub fn add_key(&mut self, key: Action) {
         self.keys.push(key);
     }
+    fn start(
+        &mut self,
+        player: Entity,
+        trick: &TrickDefinition,
+        started_events: &mut EventWriter<TrickStarted>,
+    ) {
+        started_events.send(TrickStarted {
+            player,
+            name: trick.name.clone(),
+        });
+        self.keys.clear();
+        self.last_trick_over
+            .set_duration(Duration::from_millis(trick.takes_ms as u64));
+        self.last_trick_over.reset();
+        self.last_trick_definition = Some(trick.clone());
+        self.just_started = true;
+    }
+    // A trick was found and is still being performed
+    pub fn is_executing(&self) -> bool {
+        self.last_trick_definition.is_some() && !self.last_trick_over.finished()
+    }
+    // The trick being performed and how far through it the player is, 0 to 1
+    pub fn executing(&self) -> Option<(&TrickDefinition, f32)> {
+        if !self.is_executing() {
+            return None;
+        }
+        self.last_trick_definition
+            .as_ref()
+            .map(|trick| (trick, self.last_trick_over.percent()))
+    }
 }
 
+// The player started a trick
+#[derive(Event)]
+pub struct TrickStarted {
+    pub player: Entity,
+    pub name: String,
+}
+
+// A trick was finished in the air and added to the player's combo
+#[derive(Event)]
+pub struct TrickCompleted {
+    pub player: Entity,
+    pub name: String,
+    // what the trick adds to the combo before the multiplier
+    pub points: usize,
+}
+
+// The player landed in the middle of a trick
+#[derive(Event)]
+pub struct TrickBailed {
+    pub player: Entity,
+    pub name: String,
+}
+
+// A combo was banked by landing cleanly, or lost to a bail with no points
+#[derive(Event)]
+pub struct ComboEnded {
+    pub player: Entity,
+    pub tricks: usize,
+    pub points: usize,
+}
+
+// What a trick adds to the combo it ends, less each time it's been done
+// already in the combo
+fn trick_points(combo: &[TrickDefinition], trick: &TrickDefinition, constants: &Constants) -> f32 {
+    let repeats = combo
+        .iter()
+        .filter(|earlier| earlier.name == trick.name)
+        .count();
+    trick.points as f32 * constants.combo_repeat_falloff.powi(repeats as i32)
+}
+
+// What a combo is worth once banked. Every trick after the first raises the
+// multiplier, and each time a trick is repeated it's worth less than the last
+pub fn combo_score(combo: &[TrickDefinition], constants: &Constants) -> usize {
+    let points = combo
+        .iter()
+        .enumerate()
+        .map(|(index, trick)| trick_points(&combo[..index], trick, constants))
+        .sum::<f32>();
+    let multiplier = 1. + constants.combo_multiplier_step * combo.len().saturating_sub(1) as f32;
+    (points * multiplier).round() as usize
+}
+
+// Adds the player's combo to their score and ends it
+pub fn bank_combo(
+    player: Entity,
+    trick: &mut Trick,
+    score: &mut Score,
+    constants: &Constants,
+    combo_events: &mut EventWriter<ComboEnded>,
+) {
+    if trick.combo.is_empty() {
+        return;
+    }
+    let points = combo_score(&trick.combo, constants);
+    score.0 += points;
+    combo_events.send(ComboEnded {
+        player,
+        tricks: trick.combo.len(),
+        points,
+    });
+    trick.combo.clear();
+}
+
+// Everything the trick manager lets the rest of the game know about
+#[derive(SystemParam)]
+struct TrickEvents<'w> {
+    started: EventWriter<'w, TrickStarted>,
+    completed: EventWriter<'w, TrickCompleted>,
+    bailed: EventWriter<'w, TrickBailed>,
+    combo: EventWriter<'w, ComboEnded>,
+}
+
+// Runs on the fixed step's game time, so tricks play out the same in a replay
+// and wait along with the game when it's paused or slowed down
 fn trick_manager(
     time: Res<Time>,
     constants: Res<Constants>,
-    mut object_below_query: Query<(&ShapeCaster, &ShapeHits), With<GroundedCheck>>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut trick_list: ResMut<TrickListResource>,
+    trick_trie: Res<TrickTrie>,
+    mut events: TrickEvents,
     mut player_query: Query<
-        (
-            &mut LinearVelocity,
-            &mut Trick,
-            &mut Score,
-            &mut LastKeyPressed,
-            &Transform,
-            &Collider,
-        ),
+        (Entity, &mut Trick, &mut Score, &Grounded, &FixedActions),
         With<Player>,
     >,
 ) {
-    player_query.iter_mut().next().map(
-        |(_, mut current_trick, mut score, mut last_key_pressed, transform, collider)| {
+    player_query.iter_mut().for_each(
+        |(player, mut current_trick, mut score, grounded, actions)| {
             current_trick.last_trick_over.tick(time.delta());
+            current_trick.key_window.tick(time.delta());
+            if grounded.0 {
+                current_trick.combo_window.tick(time.delta());
+            } else {
+                current_trick.combo_window.reset();
+            }
 
-            let current_ms = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
-            if current_ms - (last_key_pressed.0 .1 as u128) > constants.trick_time as u128 {
+            if current_trick.key_window.just_finished() {
+                // nothing longer came, so the keys count as the trick they already make up
+                if let Some(trick) = trick_trie
+                    .trick_at(&current_trick.keys)
+                    .filter(|_| !grounded.0)
+                {
+                    current_trick.start(player, trick, &mut events.started);
+                }
                 current_trick.keys.clear();
             }
-            let hit_point = object_below_query
-                .iter_mut()
-                .next()
-                .and_then(|(ray, hits)| {
-                    hits.iter()
-                        .next()
-                        .map(|hit| (ray.origin + ray.direction * hit.time_of_impact).y)
-                })
-                .unwrap_or(99999.);
-            let distance_to_ground = transform.translation.y
-                - hit_point
-                - collider.shape().as_cuboid().unwrap().half_extents[1];
-            if distance_to_ground < constants.grounded_threshold
+            if grounded.0
                 && !current_trick.last_trick_over.finished()
                 && current_trick.last_trick_definition.is_some()
             {
-                println!("CANCELED TRICK: {:?}", current_trick.last_trick_definition);
+                // landing mid trick is a bail and the whole combo goes with it
+                let trick = current_trick.last_trick_definition.take().unwrap();
+                events.bailed.send(TrickBailed {
+                    player,
+                    name: trick.name,
+                });
+                events.combo.send(ComboEnded {
+                    player,
+                    tricks: current_trick.combo.len() + 1,
+                    points: 0,
+                });
                 current_trick.keys.clear();
-                current_trick.last_trick_definition = None;
+                current_trick.combo.clear();
                 return;
             }
-            if current_trick.last_trick_over.just_finished() {
-                println!("last trick over, {:?}", current_trick.last_trick_definition);
+            if current_trick.combo_window.just_finished() {
+                bank_combo(
+                    player,
+                    &mut current_trick,
+                    &mut score,
+                    &constants,
+                    &mut events.combo,
+                );
             }
             if current_trick.last_trick_over.just_finished()
                 && current_trick.last_trick_definition.is_some()
             {
-                score.0 += current_trick.last_trick_definition.as_ref().unwrap().points;
-                println!("score: {}", score.0);
+                let trick = current_trick.last_trick_definition.clone().unwrap();
+                events.completed.send(TrickCompleted {
+                    player,
+                    name: trick.name.clone(),
+                    points: trick_points(&current_trick.combo, &trick, &constants).round() as usize,
+                });
+                current_trick.combo.push(trick);
                 current_trick.keys.clear();
                 return;
             }
 
-            let mut key: Option<KeyCode> = None;
-            if keyboard_input.just_pressed(KeyCode::W) {
-                key = Some(KeyCode::W);
+            // tricks are only done in the air, a stick flick on the ground is just moving
+            if grounded.0 {
+                return;
             }
-            if keyboard_input.just_pressed(KeyCode::A) {
-                key = Some(KeyCode::A);
+            let mut key: Option<Action> = None;
+            if actions.just_pressed(Action::TrickUp) {
+                key = Some(Action::TrickUp);
             }
-            if keyboard_input.just_pressed(KeyCode::S) {
-                key = Some(KeyCode::S);
+            if actions.just_pressed(Action::TrickLeft) {
+                key = Some(Action::TrickLeft);
             }
-            if keyboard_input.just_pressed(KeyCode::D) {
-                key = Some(KeyCode::D);
+            if actions.just_pressed(Action::TrickDown) {
+                key = Some(Action::TrickDown);
+            }
+            if actions.just_pressed(Action::TrickRight) {
+                key = Some(Action::TrickRight);
             }
             if key.is_none() {
                 return;
             }
 
             let current_key = key.unwrap();
-            last_key_pressed.0 = (current_key, current_ms as usize);
+            current_trick.key_window.reset();
             match key.unwrap() {
-                KeyCode::A | KeyCode::W | KeyCode::S | KeyCode::D => {
+                Action::TrickLeft | Action::TrickUp | Action::TrickDown | Action::TrickRight => {
                     if !current_trick.last_trick_over.finished() {
                         if current_trick.keys.len() > 1 {
                             current_trick.keys.clear();
@@ -135,26 +292,23 @@ fn trick_manager(
                         return;
                     }
                     current_trick.add_key(current_key);
-                    trick_list
-                        .0
-                        .find_trick(&current_trick.keys)
-                        .map(|trick| {
-                            println!("found trick: {:?}", trick.name);
-                            current_trick.keys.clear();
-                            current_trick
-                                .last_trick_over
-                                .set_duration(Duration::from_millis(trick.takes_ms as u64));
-                            current_trick.last_trick_over.reset();
-                            current_trick.last_trick_definition = Some(trick.clone());
-                            println!("executing: {:?}", trick.name);
-                        })
-                        .or_else(|| {
-                            if current_trick.keys.len() > 1 {
-                                current_trick.keys.clear();
-                                current_trick.last_trick_over.reset();
-                            };
-                            Some(())
-                        });
+                    match trick_trie.find(&current_trick.keys) {
+                        TrickMatch::Exact(trick) => {
+                            current_trick.start(player, trick, &mut events.started)
+                        }
+                        TrickMatch::Prefix => {}
+                        // the key might be the start of another trick instead
+                        TrickMatch::NoMatch => {
+                            current_trick.keys = vec![current_key];
+                            match trick_trie.find(&current_trick.keys) {
+                                TrickMatch::Exact(trick) => {
+                                    current_trick.start(player, trick, &mut events.started)
+                                }
+                                TrickMatch::Prefix => {}
+                                TrickMatch::NoMatch => current_trick.keys.clear(),
+                            }
+                        }
+                    }
                 }
                 _ => {}
             }
@@ -162,11 +316,154 @@ fn trick_manager(
     );
 }
 
+// Everything a trick's boost and hang time read or push on
+type TrickEffectsQuery = (
+    &'static mut Trick,
+    &'static mut LinearVelocity,
+    &'static mut GravityScale,
+    &'static Facing,
+    &'static Grounded,
+);
+
+// Gives the player the trick's boost when it starts and holds them in the air
+// for its hang time
+fn apply_trick_effects(mut player_query: Query<TrickEffectsQuery, With<Player>>) {
+    player_query.iter_mut().for_each(
+        |(mut trick, mut velocity, mut gravity_scale, facing, grounded)| {
+            // nothing boosts or holds up a player who's on the ground
+            let Some((definition, _)) = trick.executing().filter(|_| !grounded.0) else {
+                trick.just_started = false;
+                if trick.hanging {
+                    trick.hanging = false;
+                    gravity_scale.0 = 1.;
+                }
+                return;
+            };
+            let effects = definition.effects.clone();
+            let elapsed = trick.last_trick_over.elapsed_secs();
+            if trick.just_started {
+                trick.just_started = false;
+                let direction = if *facing == Facing::Left { -1. } else { 1. };
+                velocity.x += effects.boost_x * direction;
+                velocity.y += effects.boost_y;
+            }
+            if elapsed < effects.hang_time {
+                trick.hanging = true;
+                gravity_scale.0 = 0.;
+                velocity.y = 0.;
+            } else if trick.hanging {
+                trick.hanging = false;
+                gravity_scale.0 = 1.;
+            }
+        },
+    );
+}
+
+// Physics would take a trick's spin as the body turning, so the player is put
+// back upright before every step and only drawn spinning
+fn clear_trick_spin(mut player_query: Query<&mut Transform, With<Player>>) {
+    player_query.iter_mut().for_each(|mut transform| {
+        transform.rotation = Quat::IDENTITY;
+    });
+}
+
+fn animate_tricks(
+    mut player_query: Query<
+        (
+            &Trick,
+            &Facing,
+            &PlayerAnimations,
+            &mut Transform,
+            &mut TextureAtlasSprite,
+        ),
+        With<Player>,
+    >,
+) {
+    player_query
+        .iter_mut()
+        .for_each(|(trick, facing, animations, mut transform, mut sprite)| {
+            let Some((definition, progress)) = trick.executing() else {
+                transform.rotation = Quat::IDENTITY;
+                return;
+            };
+            match definition.animation {
+                Some(TrickAnimation::Frames { start, end }) => {
+                    let first = animations.for_state(PlayerState::Trick).start;
+                    let frame_count = end.saturating_sub(start) + 1;
+                    let frame = ((progress * frame_count as f32) as usize).min(frame_count - 1);
+                    sprite.index = first + start + frame;
+                }
+                Some(TrickAnimation::Rotation { degrees }) => {
+                    // a front flip turns toward the way the player faces
+                    let direction = if *facing == Facing::Left { 1. } else { -1. };
+                    transform.rotation =
+                        Quat::from_rotation_z((degrees * progress * direction).to_radians());
+                }
+                None => {}
+            }
+        });
+}
+
 pub struct TrickManager {
     pub scene: Scene,
 }
 impl Plugin for TrickManager {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, trick_manager.run_if(in_state(self.scene)));
+        app.add_systems(
+            FixedUpdate,
+            (
+                clear_trick_spin.before(GameplaySet),
+                (trick_manager, apply_trick_effects)
+                    .chain()
+                    .in_set(GameplaySet)
+                    .before(update_player_state),
+            )
+                .run_if(in_state(self.scene)),
+        );
+        app.add_event::<TrickStarted>();
+        app.add_event::<TrickCompleted>();
+        app.add_event::<TrickBailed>();
+        app.add_event::<ComboEnded>();
+        app.add_systems(
+            Update,
+            animate_tricks
+                .after(update_animated_sprites)
+                .after(animate_player_state)
+                .run_if(in_state(self.scene)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TrickEffects;
+
+    fn trick(name: &str, points: usize) -> TrickDefinition {
+        TrickDefinition {
+            name: name.to_string(),
+            points,
+            takes_ms: 100,
+            animation: None,
+            effects: TrickEffects::default(),
+        }
+    }
+
+    #[test]
+    fn chained_tricks_build_a_multiplier_and_repeats_score_less() {
+        let mut constants = Constants::read_from_file();
+        constants.combo_multiplier_step = 0.5;
+        constants.combo_repeat_falloff = 0.5;
+        let flip = trick("flip", 100);
+        let spin = trick("spin", 200);
+        assert_eq!(combo_score(&[], &constants), 0);
+        assert_eq!(combo_score(std::slice::from_ref(&flip), &constants), 100);
+        // (100 + 200) x1.5
+        assert_eq!(combo_score(&[flip.clone(), spin], &constants), 450);
+        // (100 + 50 + 25) x2
+        assert_eq!(
+            combo_score(&[flip.clone(), flip.clone(), flip], &constants),
+            350
+        );
     }
 }
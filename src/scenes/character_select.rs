@@ -0,0 +1,267 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::AssetServer,
+    core_pipeline::core_2d::Camera2dBundle,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::Changed,
+        schedule::{
+            common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit, State,
+        },
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    hierarchy::BuildChildren,
+    input::Input,
+    render::color::Color,
+    text::TextStyle,
+    ui::{
+        node_bundles::{AtlasImageBundle, ButtonBundle, NodeBundle, TextBundle},
+        AlignItems, BackgroundColor, FlexDirection, Interaction, JustifyContent, Style, UiRect,
+        UiTextureAtlasImage, Val,
+    },
+    utils::default,
+};
+use tiled::Loader;
+
+use crate::{
+    components_resources::SelectedCharacter,
+    models::{BelongsToScene, CharacterListResource, StatModifiers},
+    plugins::{
+        delete_manager::DeleteMe, level_loader::texture_atlas_for, player_state::PlayerState,
+    },
+    service::{bindings::Action, constants::Constants, user_stats},
+};
+
+use super::Scene;
+
+const SELECTED_COLOR: Color = Color::rgb(0.35, 0.3, 0.1);
+const UNSELECTED_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+
+#[derive(Resource)]
+struct CharacterSelect {
+    selected: usize,
+}
+
+#[derive(Component)]
+struct CharacterButton(usize);
+
+// "speed x1.15  jump x0.9", only the stats the character changes
+fn describe_modifiers(modifiers: &StatModifiers) -> String {
+    let stats = [
+        ("speed", modifiers.run_speed),
+        ("top speed", modifiers.max_speed),
+        ("jump", modifiers.jump_force),
+    ]
+    .iter()
+    .filter(|(_, multiplier)| *multiplier != 1.)
+    .map(|(name, multiplier)| format!("{} x{}", name, multiplier))
+    .collect::<Vec<_>>();
+    if stats.is_empty() {
+        "balanced".to_string()
+    } else {
+        stats.join("  ")
+    }
+}
+
+// every ui node is tagged with the scene since DeleteMe doesn't despawn children
+fn setup_character_select(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    constants: Res<Constants>,
+    characters: Res<CharacterListResource>,
+    selected_character: Res<SelectedCharacter>,
+) {
+    let font = asset_server.load("PixelifySans-VariableFont_wght.ttf");
+    let text_style = |color: Color| TextStyle {
+        font: font.clone(),
+        font_size: 30.0,
+        color,
+    };
+    let sheet = Loader::new()
+        .load_tsx_tileset(format!("./assets/{}.tsx", constants.character_sheet))
+        .unwrap();
+    let atlas = asset_server.add(texture_atlas_for(&sheet, &asset_server));
+
+    commands.spawn((
+        Camera2dBundle { ..default() },
+        BelongsToScene(Scene::CharacterSelect),
+    ));
+    commands
+        .spawn((
+            BelongsToScene(Scene::CharacterSelect),
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BelongsToScene(Scene::CharacterSelect),
+                TextBundle::from_section(
+                    "Select a character",
+                    TextStyle {
+                        font_size: 60.0,
+                        ..text_style(Color::GOLD)
+                    },
+                ),
+            ));
+            characters
+                .0
+                .characters
+                .iter()
+                .enumerate()
+                .for_each(|(index, character)| {
+                    let idle = character.animations.for_state(PlayerState::Idle);
+                    parent
+                        .spawn((
+                            BelongsToScene(Scene::CharacterSelect),
+                            CharacterButton(index),
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(700.),
+                                    padding: UiRect::all(Val::Px(10.)),
+                                    column_gap: Val::Px(20.),
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                background_color: UNSELECTED_COLOR.into(),
+                                ..default()
+                            },
+                        ))
+                        .with_children(|button| {
+                            button.spawn((
+                                BelongsToScene(Scene::CharacterSelect),
+                                AtlasImageBundle {
+                                    style: Style {
+                                        width: Val::Px(64.),
+                                        height: Val::Px(64.),
+                                        ..default()
+                                    },
+                                    texture_atlas: atlas.clone(),
+                                    texture_atlas_image: UiTextureAtlasImage {
+                                        index: idle.start,
+                                        ..default()
+                                    },
+                                    ..default()
+                                },
+                            ));
+                            button.spawn((
+                                BelongsToScene(Scene::CharacterSelect),
+                                TextBundle::from_section(
+                                    character.name.clone(),
+                                    text_style(Color::WHITE),
+                                ),
+                            ));
+                            button.spawn((
+                                BelongsToScene(Scene::CharacterSelect),
+                                TextBundle::from_section(
+                                    describe_modifiers(&character.modifiers),
+                                    text_style(Color::GRAY),
+                                ),
+                            ));
+                        });
+                });
+        });
+    // start on whoever was picked last time
+    let selected = selected_character
+        .0
+        .as_ref()
+        .and_then(|name| {
+            characters
+                .0
+                .characters
+                .iter()
+                .position(|character| &character.name == name)
+        })
+        .unwrap_or(0);
+    commands.insert_resource(CharacterSelect { selected });
+}
+
+fn update_character_select(
+    actions: Res<Input<Action>>,
+    characters: Res<CharacterListResource>,
+    mut character_select: ResMut<CharacterSelect>,
+    mut selected_character: ResMut<SelectedCharacter>,
+    button_query: Query<(&Interaction, &CharacterButton), Changed<Interaction>>,
+    mut scene_state: ResMut<NextState<Scene>>,
+) {
+    if actions.just_pressed(Action::Back) {
+        scene_state.set(Scene::Map);
+        return;
+    }
+    let count = characters.0.characters.len();
+    if actions.just_pressed(Action::MenuDown) {
+        character_select.selected = (character_select.selected + 1) % count;
+    }
+    if actions.just_pressed(Action::MenuUp) {
+        character_select.selected = (character_select.selected + count - 1) % count;
+    }
+    let mut should_nav = actions.just_pressed(Action::Confirm);
+    button_query
+        .iter()
+        .for_each(|(interaction, button)| match interaction {
+            Interaction::Hovered => character_select.selected = button.0,
+            Interaction::Pressed => {
+                character_select.selected = button.0;
+                should_nav = true;
+            }
+            Interaction::None => {}
+        });
+    if should_nav {
+        let name = &characters.0.characters[character_select.selected].name;
+        user_stats::record_character(name);
+        selected_character.0 = Some(name.clone());
+        scene_state.set(Scene::Level);
+    }
+}
+
+fn highlight_selected(
+    character_select: Res<CharacterSelect>,
+    mut button_query: Query<(&CharacterButton, &mut BackgroundColor)>,
+) {
+    button_query.iter_mut().for_each(|(button, mut color)| {
+        *color = if button.0 == character_select.selected {
+            SELECTED_COLOR.into()
+        } else {
+            UNSELECTED_COLOR.into()
+        };
+    });
+}
+
+fn exit_character_select(
+    mut commands: Commands,
+    belongs_to_scene_query: Query<(Entity, &BelongsToScene)>,
+    current_scene: Res<State<Scene>>,
+) {
+    belongs_to_scene_query
+        .iter()
+        .for_each(|(entity, owned_by_scene)| {
+            if owned_by_scene.0 != **current_scene {
+                commands.entity(entity).insert(DeleteMe {});
+            }
+        });
+    commands.remove_resource::<CharacterSelect>();
+}
+
+pub struct CharacterSelectScene;
+impl Plugin for CharacterSelectScene {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(Scene::CharacterSelect), setup_character_select);
+        app.add_systems(
+            Update,
+            (update_character_select, highlight_selected)
+                .chain()
+                .run_if(in_state(Scene::CharacterSelect)),
+        );
+        app.add_systems(OnExit(Scene::CharacterSelect), exit_character_select);
+    }
+}
@@ -0,0 +1,208 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::AssetServer,
+    core_pipeline::core_2d::Camera2dBundle,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        schedule::{
+            common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit, State,
+        },
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    hierarchy::BuildChildren,
+    input::{gamepad::GamepadButton, keyboard::KeyCode, Input},
+    render::color::Color,
+    text::{Text, TextStyle},
+    ui::{
+        node_bundles::{NodeBundle, TextBundle},
+        AlignItems, FlexDirection, JustifyContent, Style, Val,
+    },
+    utils::default,
+};
+
+use crate::{
+    models::BelongsToScene,
+    plugins::delete_manager::DeleteMe,
+    service::{
+        bindings::{Action, Bindings},
+        constants::Constants,
+    },
+};
+
+use super::Scene;
+
+#[derive(Resource)]
+struct ControlsMenu {
+    selected: usize,
+    // waiting for the key to bind to the selected action
+    listening: bool,
+}
+
+#[derive(Component)]
+struct BindingText(usize);
+
+fn describe(action: Action, bindings: &Bindings) -> String {
+    let keys = bindings
+        .keys_for(action)
+        .iter()
+        .map(|key| format!("{:?}", key))
+        .chain(
+            bindings
+                .buttons_for(action)
+                .iter()
+                .map(|button| format!("Pad {:?}", button)),
+        )
+        .collect::<Vec<String>>();
+    if keys.is_empty() {
+        format!("{:?}: unbound", action)
+    } else {
+        format!("{:?}: {}", action, keys.join(", "))
+    }
+}
+
+// every ui node is tagged with the scene since DeleteMe doesn't despawn children
+fn setup_controls(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("PixelifySans-VariableFont_wght.ttf");
+    commands.spawn((
+        Camera2dBundle { ..default() },
+        BelongsToScene(Scene::Controls),
+    ));
+    commands
+        .spawn((
+            BelongsToScene(Scene::Controls),
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(6.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BelongsToScene(Scene::Controls),
+                TextBundle::from_section(
+                    "[Up/Down] select  [Enter] rebind key or pad button  [Delete] clear  [Escape] back",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 24.0,
+                        color: Color::GOLD,
+                    },
+                ),
+            ));
+            (0..Action::ALL.len()).for_each(|index| {
+                parent.spawn((
+                    BelongsToScene(Scene::Controls),
+                    BindingText(index),
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 28.0,
+                            ..default()
+                        },
+                    ),
+                ));
+            });
+        });
+    commands.insert_resource(ControlsMenu {
+        selected: 0,
+        listening: false,
+    });
+}
+
+// Reads the keyboard directly rather than through actions,
+// otherwise a bad binding could lock the player out of fixing it
+fn update_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut menu: ResMut<ControlsMenu>,
+    mut bindings: ResMut<Bindings>,
+    constants: Res<Constants>,
+    mut scene_state: ResMut<NextState<Scene>>,
+) {
+    let action = Action::ALL[menu.selected];
+    if menu.listening {
+        if let Some(key) = keyboard_input.get_just_pressed().next() {
+            if *key != KeyCode::Escape {
+                bindings.set_keys(action, vec![*key]);
+                bindings.save_to_file(&constants);
+            }
+            menu.listening = false;
+        } else if let Some(button) = gamepad_buttons.get_just_pressed().next() {
+            bindings.set_buttons(action, vec![button.button_type]);
+            bindings.save_to_file(&constants);
+            menu.listening = false;
+        }
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        scene_state.set(Scene::Home);
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        menu.selected = (menu.selected + 1) % Action::ALL.len();
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        menu.selected = (menu.selected + Action::ALL.len() - 1) % Action::ALL.len();
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        menu.listening = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Delete) {
+        bindings.set_keys(action, vec![]);
+        bindings.set_buttons(action, vec![]);
+        bindings.save_to_file(&constants);
+    }
+}
+
+fn update_binding_text(
+    menu: Res<ControlsMenu>,
+    bindings: Res<Bindings>,
+    mut text_query: Query<(&BindingText, &mut Text)>,
+) {
+    text_query.iter_mut().for_each(|(binding_text, mut text)| {
+        let action = Action::ALL[binding_text.0];
+        let selected = binding_text.0 == menu.selected;
+        text.sections[0].value = if selected && menu.listening {
+            format!("{:?}: press a key or pad button...", action)
+        } else {
+            describe(action, &bindings)
+        };
+        text.sections[0].style.color = if selected { Color::GOLD } else { Color::WHITE };
+    });
+}
+
+fn exit_controls(
+    mut commands: Commands,
+    belongs_to_scene_query: Query<(Entity, &BelongsToScene)>,
+    current_scene: Res<State<Scene>>,
+) {
+    belongs_to_scene_query
+        .iter()
+        .for_each(|(entity, owned_by_scene)| {
+            if owned_by_scene.0 != **current_scene {
+                commands.entity(entity).insert(DeleteMe {});
+            }
+        });
+    commands.remove_resource::<ControlsMenu>();
+}
+
+pub struct ControlsScene;
+impl Plugin for ControlsScene {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(Scene::Controls), setup_controls);
+        app.add_systems(
+            Update,
+            (update_controls, update_binding_text)
+                .chain()
+                .run_if(in_state(Scene::Controls)),
+        );
+        app.add_systems(OnExit(Scene::Controls), exit_controls);
+    }
+}
@@ -0,0 +1,549 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{AssetServer, Handle},
+    core_pipeline::core_2d::Camera2d,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        schedule::{
+            apply_deferred, common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter,
+            OnExit,
+        },
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    input::{keyboard::KeyCode, mouse::MouseButton, Input},
+    log::warn,
+    math::{Vec2, Vec3},
+    render::{camera::Camera, color::Color, view::Visibility},
+    sprite::{Sprite, SpriteBundle, SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
+    text::{Text, TextSection, TextStyle},
+    time::{Real, Time, Virtual},
+    transform::components::{GlobalTransform, Transform},
+    ui::node_bundles::TextBundle,
+    window::{PrimaryWindow, Window},
+};
+use tiled::{Loader, ObjectShape, PropertyValue};
+
+use crate::{
+    components_resources::{Enemy, ObjectComponent, Player, TiledMap},
+    models::BelongsToScene,
+    plugins::{
+        delete_manager::DeleteMe,
+        ghost::GhostRecorder,
+        level_loader::{initialize_checkmarks, initialize_map_collisions, texture_atlas_for},
+        replay::{ReplayPlayback, ReplayRecorder},
+    },
+    service::{
+        bindings::Action,
+        tmx_file::{TmxFile, GID_FLIPPED_HORIZONTALLY},
+    },
+};
+
+use super::{
+    level::{LevelID, LevelState},
+    Scene,
+};
+
+const CAMERA_SPEED: f32 = 400.;
+// draw objects above every tile layer
+const OBJECT_Z: f32 = 50.;
+
+#[derive(Resource)]
+pub struct Editor {
+    file: TmxFile,
+    // atlas and tile count for every tileset, in the order of file.first_gids
+    tilesets: Vec<(Handle<TextureAtlas>, u32)>,
+    layer: usize,
+    brush: u32,
+    // id of the object being dragged around
+    dragging: Option<u32>,
+    camera: Vec2,
+}
+
+impl Editor {
+    // Every gid that points at an actual tile
+    fn brushes(&self) -> Vec<u32> {
+        self.file
+            .first_gids
+            .iter()
+            .zip(self.tilesets.iter())
+            .flat_map(|(first_gid, (_, tile_count))| *first_gid..*first_gid + tile_count)
+            .collect()
+    }
+
+    // Tiles are centered on (col * width, -row * height), like the level loader places them
+    fn tile_position(&self, col: u32, row: u32) -> Vec2 {
+        Vec2::new(
+            (col * self.file.tile_width) as f32,
+            -((row * self.file.tile_height) as f32),
+        )
+    }
+
+    fn tile_at(&self, position: Vec2) -> Option<(u32, u32)> {
+        let col = (position.x / self.file.tile_width as f32).round();
+        let row = (-position.y / self.file.tile_height as f32).round();
+        if col < 0. || row < 0. {
+            return None;
+        }
+        Some((col as u32, row as u32))
+    }
+}
+
+// The map the editor is being opened on, parsed before the level is paused
+#[derive(Resource)]
+struct OpenedFile(TmxFile);
+
+// Everything the editor spawns, cleared out when play resumes
+#[derive(Component)]
+struct EditorOnly;
+
+// The level's own tiles and end checkpoints, which get swapped for the edited
+// ones when play resumes
+type LevelTiles = (With<ObjectComponent>, Without<Enemy>);
+
+#[derive(Component)]
+struct EditorTile {
+    layer: usize,
+    col: u32,
+    row: u32,
+}
+
+#[derive(Component)]
+struct EditorObject {
+    id: u32,
+    size: Vec2,
+}
+
+#[derive(Component)]
+struct BrushPreview;
+
+#[derive(Component)]
+struct EditorText;
+
+fn spawn_tile(commands: &mut Commands, editor: &Editor, layer: usize, col: u32, row: u32) {
+    let gid = editor.file.gid(layer, col, row);
+    if let Some((tileset, index)) = editor.file.tileset_of(gid) {
+        commands.spawn((
+            BelongsToScene(Scene::Level),
+            EditorOnly,
+            EditorTile { layer, col, row },
+            SpriteSheetBundle {
+                transform: Transform::from_translation(
+                    editor.tile_position(col, row).extend(layer as f32),
+                ),
+                sprite: TextureAtlasSprite {
+                    flip_x: gid & GID_FLIPPED_HORIZONTALLY != 0,
+                    index: index as usize,
+                    ..Default::default()
+                },
+                texture_atlas: editor.tilesets[tileset].0.clone(),
+                ..Default::default()
+            },
+        ));
+    }
+}
+
+fn cursor_position(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec2> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (camera, camera_transform) = camera_query.iter().next()?;
+    camera.viewport_to_world_2d(camera_transform, cursor)
+}
+
+// Pauses the running level and opens it up in the editor. A run that was
+// edited partway through can't be saved as a replay or a ghost, and a replay
+// being watched can't be edited at all
+fn open_editor(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    level_id: Res<LevelID>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut level_state: ResMut<NextState<LevelState>>,
+) {
+    if !actions.just_pressed(Action::ToggleEditor) || playback.is_some() {
+        return;
+    }
+    let file = match TmxFile::read(&level_id.0) {
+        Ok(file) => file,
+        Err(error) => {
+            warn!("can't edit {}: {}", level_id.0, error);
+            return;
+        }
+    };
+    commands.insert_resource(OpenedFile(file));
+    commands.remove_resource::<ReplayRecorder>();
+    commands.remove_resource::<GhostRecorder>();
+    level_state.set(LevelState::Editing);
+}
+
+fn initialize_editor(
+    mut commands: Commands,
+    opened_file: Res<OpenedFile>,
+    map: Res<TiledMap>,
+    asset_server: Res<AssetServer>,
+    mut time: ResMut<Time<Virtual>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut level_tile_query: Query<&mut Visibility, LevelTiles>,
+) {
+    // nothing moves while the level is being edited
+    time.pause();
+    // the editor draws the tiles it's changing itself
+    level_tile_query.iter_mut().for_each(|mut visibility| {
+        *visibility = Visibility::Hidden;
+    });
+    let file = opened_file.0.clone();
+    commands.remove_resource::<OpenedFile>();
+    let tilesets = map
+        .0
+        .tilesets()
+        .iter()
+        .map(|tileset| {
+            (
+                asset_server.add(texture_atlas_for(tileset, &asset_server)),
+                tileset.tilecount,
+            )
+        })
+        .collect();
+    let tile_size = Vec2::new(file.tile_width as f32, file.tile_height as f32);
+
+    // spawns and checkpoints can be dragged around
+    let mut player_spawn = None;
+    map.0.layers().for_each(|layer| {
+        if let Some(object_layer) = layer.as_object_layer() {
+            object_layer.objects().for_each(|object| {
+                let property = |name: &str| match object.properties.get(name) {
+                    Some(PropertyValue::StringValue(value)) => Some(value.clone()),
+                    _ => None,
+                };
+                let color = match (
+                    property("spawn").as_deref(),
+                    property("checkpoint").as_deref(),
+                ) {
+                    (Some("player"), _) => {
+                        player_spawn = Some(Vec2::new(object.x, -object.y));
+                        Color::rgba(0., 1., 0., 0.6)
+                    }
+                    (Some(_), _) => Color::rgba(1., 0.5, 0., 0.6),
+                    (_, Some(_)) => Color::rgba(1., 0., 0., 0.4),
+                    _ => return,
+                };
+                let size = match object.shape {
+                    ObjectShape::Rect { width, height } if width > 0. && height > 0. => {
+                        Vec2::new(width, height)
+                    }
+                    _ => tile_size,
+                };
+                commands.spawn((
+                    BelongsToScene(Scene::Level),
+                    EditorOnly,
+                    EditorObject {
+                        id: object.id(),
+                        size,
+                    },
+                    SpriteBundle {
+                        transform: Transform::from_xyz(object.x, -object.y, OBJECT_Z),
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(size),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ));
+            });
+        }
+    });
+
+    // start out looking at the players, where play picks back up from
+    let camera = player_query
+        .iter()
+        .next()
+        .map(|transform| transform.translation.truncate())
+        .or(player_spawn)
+        .unwrap_or(Vec2::ZERO);
+
+    let editor = Editor {
+        file,
+        tilesets,
+        layer: 0,
+        brush: 1,
+        dragging: None,
+        camera,
+    };
+    (0..editor.file.layers.len()).for_each(|layer| {
+        (0..editor.file.layers[layer].height).for_each(|row| {
+            (0..editor.file.layers[layer].width).for_each(|col| {
+                spawn_tile(&mut commands, &editor, layer, col, row);
+            });
+        });
+    });
+    commands.spawn((
+        BelongsToScene(Scene::Level),
+        EditorOnly,
+        BrushPreview,
+        SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                color: Color::rgba(1., 1., 1., 0.6),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ));
+
+    let font = asset_server.load("PixelifySans-VariableFont_wght.ttf");
+    commands.spawn((
+        BelongsToScene(Scene::Level),
+        EditorOnly,
+        EditorText,
+        TextBundle::from_sections([
+            TextSection::new(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 24.0,
+                    color: Color::GOLD,
+                },
+            ),
+            TextSection::new(
+                "\n[LMB] paint/drag  [RMB] erase  [Q/E] tile  [L] layer  [Arrows] move  [Ctrl+S] save  [F2] play",
+                TextStyle {
+                    font,
+                    font_size: 18.0,
+                    ..Default::default()
+                },
+            ),
+        ]),
+    ));
+    commands.insert_resource(editor);
+}
+
+// Runs on real time, the level's clock is stopped while editing
+fn move_camera(
+    time: Res<Time<Real>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    let mut direction = Vec2::ZERO;
+    if keyboard_input.pressed(KeyCode::Left) {
+        direction.x -= 1.;
+    }
+    if keyboard_input.pressed(KeyCode::Right) {
+        direction.x += 1.;
+    }
+    if keyboard_input.pressed(KeyCode::Up) {
+        direction.y += 1.;
+    }
+    if keyboard_input.pressed(KeyCode::Down) {
+        direction.y -= 1.;
+    }
+    editor.camera += direction * CAMERA_SPEED * time.delta_seconds();
+    camera_query.iter_mut().for_each(|mut transform| {
+        transform.translation.x = editor.camera.x;
+        transform.translation.y = editor.camera.y;
+    });
+}
+
+fn change_brush(keyboard_input: Res<Input<KeyCode>>, mut editor: ResMut<Editor>) {
+    let brushes = editor.brushes();
+    if !brushes.is_empty() {
+        let current = brushes
+            .iter()
+            .position(|brush| *brush == editor.brush)
+            .unwrap_or(0);
+        if keyboard_input.just_pressed(KeyCode::E) {
+            editor.brush = brushes[(current + 1) % brushes.len()];
+        }
+        if keyboard_input.just_pressed(KeyCode::Q) {
+            editor.brush = brushes[(current + brushes.len() - 1) % brushes.len()];
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::L) && !editor.file.layers.is_empty() {
+        editor.layer = (editor.layer + 1) % editor.file.layers.len();
+    }
+}
+
+fn edit_with_mouse(
+    mut commands: Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut editor: ResMut<Editor>,
+    tile_query: Query<(Entity, &EditorTile)>,
+    mut object_query: Query<(&EditorObject, &mut Transform)>,
+) {
+    if mouse_input.just_released(MouseButton::Left) {
+        editor.dragging = None;
+    }
+    let cursor = match cursor_position(&windows, &camera_query) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    if mouse_input.just_pressed(MouseButton::Left) {
+        editor.dragging = object_query
+            .iter()
+            .find(|(object, transform)| {
+                let offset = (cursor - transform.translation.truncate()).abs();
+                offset.x <= object.size.x / 2. && offset.y <= object.size.y / 2.
+            })
+            .map(|(object, _)| object.id);
+    }
+    if let Some(id) = editor.dragging {
+        object_query
+            .iter_mut()
+            .filter(|(object, _)| object.id == id)
+            .for_each(|(_, mut transform)| {
+                transform.translation.x = cursor.x;
+                transform.translation.y = cursor.y;
+            });
+        // tiled has y pointing down
+        editor.file.set_object_position(id, cursor.x, -cursor.y);
+        return;
+    }
+    let gid = if mouse_input.pressed(MouseButton::Left) {
+        editor.brush
+    } else if mouse_input.pressed(MouseButton::Right) {
+        0
+    } else {
+        return;
+    };
+    let layer = editor.layer;
+    if let Some((col, row)) = editor.tile_at(cursor) {
+        if editor.file.set_gid(layer, col, row, gid) {
+            tile_query
+                .iter()
+                .filter(|(_, tile)| tile.layer == layer && tile.col == col && tile.row == row)
+                .for_each(|(entity, _)| {
+                    commands.entity(entity).insert(DeleteMe);
+                });
+            spawn_tile(&mut commands, &editor, layer, col, row);
+        }
+    }
+}
+
+fn update_brush_preview(
+    editor: Res<Editor>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut preview_query: Query<
+        (&mut Transform, &mut TextureAtlasSprite, &mut Handle<TextureAtlas>),
+        With<BrushPreview>,
+    >,
+) {
+    let tile = cursor_position(&windows, &camera_query).and_then(|cursor| editor.tile_at(cursor));
+    preview_query
+        .iter_mut()
+        .for_each(|(mut transform, mut sprite, mut atlas)| {
+            if let (Some((col, row)), Some((tileset, index))) =
+                (tile, editor.file.tileset_of(editor.brush))
+            {
+                transform.translation = editor.tile_position(col, row).extend(OBJECT_Z - 1.);
+                sprite.index = index as usize;
+                *atlas = editor.tilesets[tileset].0.clone();
+            } else {
+                transform.translation = Vec3::new(0., 0., -1000.);
+            }
+        });
+}
+
+fn update_editor_text(editor: Res<Editor>, mut text_query: Query<&mut Text, With<EditorText>>) {
+    let layer_name = editor
+        .file
+        .layers
+        .get(editor.layer)
+        .map(|layer| layer.name.clone())
+        .unwrap_or_default();
+    text_query.iter_mut().for_each(|mut text| {
+        text.sections[0].value = format!("layer: {}  tile: {}", layer_name, editor.brush);
+    });
+}
+
+fn save_and_resume(
+    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
+    level_id: Res<LevelID>,
+    editor: Res<Editor>,
+    mut level_state: ResMut<NextState<LevelState>>,
+) {
+    let ctrl = keyboard_input.pressed(KeyCode::ControlLeft)
+        || keyboard_input.pressed(KeyCode::ControlRight);
+    if ctrl && keyboard_input.just_pressed(KeyCode::S) {
+        editor.file.save(&level_id.0);
+    }
+    // the tiles get loaded from the file again, so play picks up the edits
+    if actions.just_pressed(Action::ToggleEditor) {
+        editor.file.save(&level_id.0);
+        level_state.set(LevelState::PrePlay);
+    }
+}
+
+fn close_editor(
+    mut commands: Commands,
+    mut time: ResMut<Time<Virtual>>,
+    editor_query: Query<Entity, With<EditorOnly>>,
+) {
+    time.unpause();
+    editor_query.iter().for_each(|entity| {
+        commands.entity(entity).insert(DeleteMe);
+    });
+    commands.remove_resource::<Editor>();
+}
+
+// Swaps the level's tiles and end checkpoints for the saved ones. Players,
+// enemies and whatever's been collected carry on from where they were
+fn reload_level_tiles(
+    mut commands: Commands,
+    level_id: Res<LevelID>,
+    level_tile_query: Query<Entity, LevelTiles>,
+) {
+    level_tile_query.iter().for_each(|entity| {
+        commands.entity(entity).insert(DeleteMe);
+    });
+    let map = Loader::new()
+        .load_tmx_map(format!("./assets/{}", level_id.0))
+        .unwrap();
+    commands.insert_resource(TiledMap(map));
+}
+
+pub struct EditorScene;
+impl Plugin for EditorScene {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            open_editor
+                .run_if(in_state(Scene::Level))
+                .run_if(in_state(LevelState::PrePlay)),
+        );
+        app.add_systems(OnEnter(LevelState::Editing), initialize_editor);
+        app.add_systems(
+            Update,
+            (
+                move_camera,
+                change_brush,
+                edit_with_mouse,
+                update_brush_preview,
+                update_editor_text,
+                save_and_resume,
+            )
+                .chain()
+                .run_if(in_state(LevelState::Editing)),
+        );
+        app.add_systems(
+            OnExit(LevelState::Editing),
+            (
+                close_editor,
+                // leaving the level from the editor has nothing to reload
+                (
+                    reload_level_tiles,
+                    apply_deferred,
+                    (initialize_map_collisions, initialize_checkmarks),
+                )
+                    .chain()
+                    .run_if(in_state(Scene::Level)),
+            ),
+        );
+    }
+}
@@ -1,40 +1,89 @@
 use bevy::{
     app::{App, Plugin, Update},
+    asset::AssetServer,
     core_pipeline::core_2d::Camera2dBundle,
     ecs::{
+        entity::Entity,
         schedule::{
             common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit, State,
         },
-        system::{Commands, Res, ResMut},
-    },
-    input::{
-        keyboard::{KeyCode, KeyboardInput},
-        Input,
+        system::{Commands, Query, Res, ResMut},
     },
+    input::Input,
     math::Vec2,
-    render::{color::Color, render_resource::Texture},
+    render::color::Color,
     sprite::{Sprite, SpriteBundle},
-    transform::{commands, components::Transform},
+    text::TextStyle,
+    transform::components::Transform,
+    ui::node_bundles::TextBundle,
     utils::default,
 };
 
+use crate::{
+    models::BelongsToScene,
+    plugins::delete_manager::DeleteMe,
+    service::bindings::{Action, Bindings},
+};
+
 use super::Scene;
 
-fn setup_home(mut commands: Commands) {
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            color: Color::hex("FF0000").unwrap(),
-            custom_size: Some(Vec2::new(100.0, 100.0)),
+fn setup_home(mut commands: Commands, asset_server: Res<AssetServer>, bindings: Res<Bindings>) {
+    commands.spawn((
+        BelongsToScene(Scene::Home),
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::hex("FF0000").unwrap(),
+                custom_size: Some(Vec2::new(100.0, 100.0)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 0.0),
             ..Default::default()
         },
-        transform: Transform::from_xyz(0.0, 0.0, 0.0),
-        ..Default::default()
-    });
+    ));
+    commands.spawn((Camera2dBundle { ..default() }, BelongsToScene(Scene::Home)));
+    let key_name = |action: Action| {
+        bindings
+            .keys_for(action)
+            .first()
+            .map(|key| format!("{:?}", key))
+            .unwrap_or_else(|| "unbound".to_string())
+    };
+    commands.spawn((
+        BelongsToScene(Scene::Home),
+        TextBundle::from_section(
+            format!(
+                "[{}] play  [{}] controls",
+                key_name(Action::Confirm),
+                key_name(Action::Settings)
+            ),
+            TextStyle {
+                font: asset_server.load("PixelifySans-VariableFont_wght.ttf"),
+                font_size: 30.0,
+                ..default()
+            },
+        ),
+    ));
 }
-fn update_home(mut scene_state: ResMut<NextState<Scene>>, keyboard_input: Res<Input<KeyCode>>) {
-    if keyboard_input.just_pressed(KeyCode::Space) {
+fn update_home(mut scene_state: ResMut<NextState<Scene>>, actions: Res<Input<Action>>) {
+    if actions.just_pressed(Action::Confirm) {
         scene_state.set(Scene::Map);
     }
+    if actions.just_pressed(Action::Settings) {
+        scene_state.set(Scene::Controls);
+    }
+}
+fn exit_home(
+    mut commands: Commands,
+    belongs_to_scene_query: Query<(Entity, &BelongsToScene)>,
+    current_scene: Res<State<Scene>>,
+) {
+    belongs_to_scene_query
+        .iter()
+        .for_each(|(entity, owned_by_scene)| {
+            if owned_by_scene.0 != **current_scene {
+                commands.entity(entity).insert(DeleteMe {});
+            }
+        });
 }
 
 pub struct HomeScene;
@@ -42,5 +91,6 @@ impl Plugin for HomeScene {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(Scene::Home), setup_home);
         app.add_systems(Update, update_home.run_if(in_state(Scene::Home)));
+        app.add_systems(OnExit(Scene::Home), exit_home);
     }
 }
@@ -1,16 +1,17 @@
 use std::time::Duration;
 
 use bevy::{
-    a11y::accesskit::Rect, app::{App, Plugin, Update}, asset::AssetServer, ecs::{
+    app::{App, FixedUpdate, Plugin, Update}, asset::AssetServer, ecs::{
         component::Component,
         entity::Entity,
+        event::{EventReader, EventWriter},
         query::With,
         schedule::{
-            common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit,
+            common_conditions::in_state, Condition, IntoSystemConfigs, NextState, OnEnter, OnExit,
             ScheduleLabel, State, States,
         },
-        system::{Commands, NonSend, Query, Res, ResMut, Resource},
-    }, input::{keyboard::KeyCode, Input}, log::Level, render::{color::Color, view::window}, text::{Text, TextSection, TextStyle}, time::{Stopwatch, Time, Timer}, transform::{commands, components::Transform}, ui::{node_bundles::TextBundle, AlignSelf, PositionType, Style, Val}, utils::default, window::{PrimaryWindow, Window, WindowResolution}, winit::WinitWindows
+        system::{Commands, Query, Res, ResMut, Resource},
+    }, hierarchy::Parent, input::Input, render::color::Color, text::{Text, TextSection, TextStyle}, time::{Stopwatch, Time, Timer}, transform::components::Transform, ui::{node_bundles::TextBundle, AlignSelf, PositionType, Style, UiRect, Val}, utils::default, window::{PrimaryWindow, Window},
 };
 use bevy_xpbd_2d::{
     components::Collider,
@@ -19,13 +20,26 @@ use bevy_xpbd_2d::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    components_resources::{CheckpointCheck, Player, Score},
+    components_resources::{
+        CheckpointCheck, CollectedThisRun, Collectible, Player, PlayerNumber, Score, Size,
+    },
     models::BelongsToScene,
     plugins::{
-        camera_controls::CameraControls, delete_manager::DeleteMe, level_loader::LevelLoader,
-        physics::PhysicsManager, player_manager::PlayerManager, trick_manager::TrickManager,
+        camera_controls::CameraControls,
+        delete_manager::DeleteMe,
+        ghost::{save_ghost_on_completed, GhostManager},
+        health::{Health, HealthManager, Lives, OutOfLives},
+        level_loader::LevelLoader,
+        physics::{GameplaySet, PhysicsManager},
+        player_manager::PlayerManager,
+        player_state::update_player_state,
+        replay::{finish_replay_on_completed, finish_replay_on_failed, ReplayManager, ReplayPlayback},
+        trick_manager::{
+            bank_combo, ComboEnded, Trick, TrickBailed, TrickCompleted, TrickManager, TrickStarted,
+        },
     },
     service::{
+        bindings::Action,
         constants::Constants,
         user_stats::{self, LevelResult},
     },
@@ -34,40 +48,110 @@ use crate::{
 use super::Scene;
 
 fn update_level(
-    time: Res<Time>,
     mut scene_state: ResMut<NextState<Scene>>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut level_stopwatch: ResMut<LevelStopwatch>,
+    actions: Res<Input<Action>>,
 ) {
-    level_stopwatch.0.tick(time.delta());
-    if keyboard_input.just_pressed(KeyCode::Space) {
+    if actions.just_pressed(Action::Pause) {
         scene_state.set(Scene::Home);
     }
 }
 
+// Level time is counted in fixed steps so a replay of the run finishes in
+// exactly the same time
+fn tick_level_stopwatch(time: Res<Time>, mut level_stopwatch: ResMut<LevelStopwatch>) {
+    level_stopwatch.0.tick(time.delta());
+}
+
+fn reset_level_stopwatch(mut commands: Commands) {
+    commands.insert_resource(LevelStopwatch(Stopwatch::new()));
+}
+
+// The result is taken on the step the checkmark is reached, the level keeps
+// stepping until the state changes and nothing after that should count
 fn hit_checkmark(
     constants: Res<Constants>,
+    level_id: Res<LevelID>,
+    mut level_stopwatch: ResMut<LevelStopwatch>,
+    mut level_result: ResMut<LevelResult>,
     player_query: Query<(&Transform, &Collider), With<Player>>,
-    checkmark_query: Query<(&RayCaster, &RayHits), With<CheckpointCheck>>,
+    mut score_query: Query<(Entity, &mut Trick, &mut Score), With<Player>>,
+    checkmark_query: Query<(&RayCaster, &RayHits, &Parent), With<CheckpointCheck>>,
     mut scene_state: ResMut<NextState<LevelState>>,
+    mut combo_events: EventWriter<ComboEnded>,
 ) {
-    if let Some((ray, hits)) = checkmark_query.iter().next() {
-        hits.iter_sorted().next().map(|hit| {
-            let hit_point = ray.origin + ray.direction * hit.time_of_impact;
-            let distance_to_hit = player_query
-                .iter()
-                .next()
-                .map(|(transform, collider)| {
-                    let player_y = transform.translation.y
-                        - collider.shape().as_cuboid().unwrap().half_extents[1];
-                    player_y - hit_point.y
-                })
-                .unwrap_or(99999.);
-            if constants.grounded_threshold < distance_to_hit {
-                return;
-            }
-            scene_state.set(LevelState::Over);
-        });
+    if level_stopwatch.finished() {
+        return;
+    }
+    // whoever reaches the checkmark first ends the level for everyone
+    let reached = checkmark_query.iter().any(|(ray, hits, parent)| {
+        let Some(hit) = hits.iter_sorted().next() else {
+            return false;
+        };
+        let hit_point = ray.global_origin() + ray.global_direction() * hit.time_of_impact;
+        let distance_to_hit = player_query
+            .get(parent.get())
+            .map(|(transform, collider)| {
+                let player_y = transform.translation.y
+                    - collider.shape().as_cuboid().unwrap().half_extents[1];
+                player_y - hit_point.y
+            })
+            .unwrap_or(99999.);
+        distance_to_hit <= constants.grounded_threshold
+    });
+    if !reached {
+        return;
+    }
+    level_stopwatch.0.pause();
+    // combos still going when the level ends count, they'd be banked on landing anyway
+    score_query.iter_mut().for_each(|(player, mut trick, mut score)| {
+        bank_combo(player, &mut trick, &mut score, &constants, &mut combo_events);
+    });
+    // the best score among the players is the one that gets recorded
+    level_result.level_id = level_id.0.clone();
+    level_result.time = level_stopwatch.0.elapsed().as_millis() as usize;
+    level_result.score = score_query.iter().map(|(_, _, score)| score.0).max().unwrap_or(0);
+    scene_state.set(LevelState::Over);
+}
+
+fn pick_up_collectibles(
+    mut commands: Commands,
+    level_stopwatch: Res<LevelStopwatch>,
+    mut collected: ResMut<CollectedThisRun>,
+    player_query: Query<(&Transform, &Collider), With<Player>>,
+    collectible_query: Query<(Entity, &Transform, &Size, &Collectible)>,
+) {
+    if level_stopwatch.finished() {
+        return;
+    }
+    player_query.iter().for_each(|(player_transform, collider)| {
+        let half_extents = collider.shape().as_cuboid().unwrap().half_extents;
+        collectible_query
+            .iter()
+            .for_each(|(entity, transform, size, collectible)| {
+                let offset = player_transform.translation - transform.translation;
+                if offset.x.abs() < half_extents[0] + size.width / 2.
+                    && offset.y.abs() < half_extents[1] + size.height / 2.
+                    && !collected.0.contains(&collectible.0)
+                {
+                    commands.entity(entity).insert(DeleteMe);
+                    collected.0.push(collectible.0);
+                }
+            });
+    });
+}
+
+// The level only fails once every player is out of lives
+fn fail_level(
+    mut out_of_lives_events: EventReader<OutOfLives>,
+    level_stopwatch: Res<LevelStopwatch>,
+    mut level_state: ResMut<NextState<LevelState>>,
+    player_query: Query<&Lives, With<Player>>,
+) {
+    if out_of_lives_events.read().next().is_some()
+        && !level_stopwatch.finished()
+        && player_query.iter().all(|lives| lives.0 == 0)
+    {
+        level_state.set(LevelState::Failed);
     }
 }
 
@@ -88,11 +172,15 @@ fn cleanup(
 }
 
 #[derive(States, Default, Clone, Copy, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
-enum LevelState {
+pub enum LevelState {
     #[default]
     PrePlay,
     Playing,
     Over,
+    // ran out of lives, nothing gets recorded
+    Failed,
+    // paused and opened up in the editor
+    Editing,
 }
 
 #[derive(Resource, Clone, Debug)]
@@ -104,20 +192,16 @@ pub struct EndLevelTimer(Timer);
 fn handle_enter_post_game(
     constants: Res<Constants>,
     mut commands: Commands,
-    mut level_result: ResMut<LevelResult>,
-    level_stopwatch: ResMut<LevelStopwatch>,
+    level_result: Res<LevelResult>,
     level_id: Res<LevelID>,
-    player_query: Query<&Score, With<Score>>,
+    collected: Res<CollectedThisRun>,
+    playback: Option<Res<ReplayPlayback>>,
 ) {
-    let player_score = player_query
-        .iter()
-        .next()
-        .and_then(|score| Some(score.0))
-        .unwrap_or(0);
-    level_result.level_id = level_id.0.clone();
-    level_result.time = level_stopwatch.0.elapsed().as_millis() as usize;
-    level_result.score = player_score;
-    user_stats::record_level_result_to_user_stats(level_result.clone());
+    // a replay re-runs someone else's finish, it isn't one of the player's own
+    if playback.is_none() {
+        user_stats::record_level_result_to_user_stats(level_result.clone());
+        user_stats::record_collected_gems(&level_id.0, &collected.0);
+    }
     commands.insert_resource(EndLevelTimer(Timer::new(
         Duration::from_secs(constants.post_level_secs),
         bevy::time::TimerMode::Once,
@@ -140,8 +224,52 @@ fn handle_post_game_update(
 #[derive(Component)]
 struct PointsText;
 
-#[derive(Resource, Clone, Debug)]
+#[derive(Component)]
+struct HealthText;
+
+const TRICK_POPUP_SECS: f32 = 1.5;
+
+// Shows the trick being done, or the last trick, combo or bail, and fades out
+#[derive(Component)]
+struct TrickPopup(Timer);
+
+// Every trick landed this run by name and how many times, in the order they
+// were first done
+#[derive(Resource, Default)]
+struct PerformedTricks(Vec<(String, usize)>);
+
+fn reset_performed_tricks(mut commands: Commands) {
+    commands.insert_resource(PerformedTricks::default());
+}
+
+fn record_performed_tricks(
+    level_stopwatch: Res<LevelStopwatch>,
+    mut performed_tricks: ResMut<PerformedTricks>,
+    mut completed_events: EventReader<TrickCompleted>,
+) {
+    completed_events.read().for_each(|completed| {
+        if level_stopwatch.finished() {
+            return;
+        }
+        match performed_tricks
+            .0
+            .iter_mut()
+            .find(|(name, _)| *name == completed.name)
+        {
+            Some((_, count)) => *count += 1,
+            None => performed_tricks.0.push((completed.name.clone(), 1)),
+        }
+    });
+}
+
+#[derive(Resource, Clone, Debug, Default)]
 pub struct LevelStopwatch(Stopwatch);
+impl LevelStopwatch {
+    // The clock stops on the step a player reaches the end
+    pub fn finished(&self) -> bool {
+        self.0.paused()
+    }
+}
 
 fn initialize_gui(mut commands: Commands, mut level_state: ResMut<NextState<LevelState>>,  asset_server: Res<AssetServer>) {
     // Text with multiple sections
@@ -162,7 +290,7 @@ fn initialize_gui(mut commands: Commands, mut level_state: ResMut<NextState<Leve
             TextSection::new(
                 "0",
                 TextStyle {
-                    font: font,
+                    font: font.clone(),
                     font_size: 60.0,
                     color: Color::GOLD,
                 },
@@ -170,6 +298,88 @@ fn initialize_gui(mut commands: Commands, mut level_state: ResMut<NextState<Leve
         ]),
         PointsText,
     ));
+    commands.spawn((
+        BelongsToScene(Scene::Level),
+        TextBundle {
+            text: Text::from_sections([
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 40.0,
+                        color: Color::RED,
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font,
+                        font_size: 40.0,
+                        ..default()
+                    },
+                ),
+            ]),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(70.),
+                left: Val::Px(0.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        HealthText,
+    ));
+    commands.spawn((
+        BelongsToScene(Scene::Level),
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("PixelifySans-VariableFont_wght.ttf"),
+                    font_size: 40.0,
+                    color: Color::GOLD,
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.),
+                right: Val::Px(20.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        TrickPopup(Timer::from_seconds(TRICK_POPUP_SECS, bevy::time::TimerMode::Once)),
+    ));
+}
+
+fn create_failed_gui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("PixelifySans-VariableFont_wght.ttf");
+    commands.spawn((
+        BelongsToScene(Scene::Level),
+        TextBundle {
+            text: Text::from_section(
+                "Out of lives",
+                TextStyle {
+                    font,
+                    font_size: 80.0,
+                    color: Color::RED,
+                },
+            ),
+            style: Style {
+                align_self: AlignSelf::Center,
+                margin: UiRect::horizontal(Val::Auto),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ));
+}
+
+fn handle_enter_failed(constants: Res<Constants>, mut commands: Commands) {
+    commands.insert_resource(EndLevelTimer(Timer::new(
+        Duration::from_secs(constants.post_level_secs),
+        bevy::time::TimerMode::Once,
+    )));
 }
 
 /**
@@ -183,6 +393,7 @@ fn create_post_game_gui(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     level_result: Res<LevelResult>,
+    performed_tricks: Res<PerformedTricks>,
     windows: Query<&Window, With<PrimaryWindow>>,
     player_query: Query<&Score, With<Player>>,
 ) {
@@ -273,23 +484,164 @@ fn create_post_game_gui(
             ..Default::default()
         },
     ));
+    // tricks text
+    let tricks = performed_tricks
+        .0
+        .iter()
+        .map(|(name, count)| {
+            if *count > 1 {
+                format!("{} x{}", name, count)
+            } else {
+                name.clone()
+            }
+        })
+        .collect::<Vec<_>>();
+    if tricks.is_empty() {
+        return;
+    }
+    commands.spawn((
+        BelongsToScene(Scene::Level),
+        TextBundle {
+            text: Text::from_sections([
+                TextSection::new(
+                    "Tricks: ",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 40.0,
+                        ..default()
+                    },
+                ),
+                TextSection::new(
+                    tricks.join(", "),
+                    TextStyle {
+                        font,
+                        font_size: 40.0,
+                        color: Color::GOLD,
+                    },
+                ),
+            ]),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(window.0 / 1.63),
+                top: Val::Px(window.1 / 1.63 + 80.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ));
+}
+
+// Players only need telling apart once there is more than one of them
+fn player_label(number: &PlayerNumber, player_count: usize) -> String {
+    if player_count > 1 {
+        format!("P{} ", number.0 + 1)
+    } else {
+        "".to_string()
+    }
 }
 
 fn update_gui(
     mut text_query: Query<(&mut Transform, &mut Text), With<PointsText>>,
-    player_query: Query<&Score, With<Score>>,
+    player_query: Query<(&PlayerNumber, &Score), With<Player>>,
 ) {
-    player_query.iter().next().and_then(|score| {
-        text_query
-            .iter_mut()
-            .next()
-            .and_then(|(mut transform, mut text)| {
-                text.sections[1].value = score.0.to_string();
-                transform.translation.x = 0.0;
-                transform.translation.y = 0.0;
-                Some(())
-            });
-        Some(())
+    let mut players = player_query.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(number, _)| number.0);
+    let scores = players
+        .iter()
+        .map(|(number, score)| format!("{}{}", player_label(number, players.len()), score.0))
+        .collect::<Vec<_>>()
+        .join("  ");
+    if let Some((mut transform, mut text)) = text_query.iter_mut().next() {
+        text.sections[1].value = scores;
+        transform.translation.x = 0.0;
+        transform.translation.y = 0.0;
+    }
+}
+
+// The newest trick event wins the popup, which then fades out
+fn update_trick_popup(
+    time: Res<Time>,
+    mut started_events: EventReader<TrickStarted>,
+    mut completed_events: EventReader<TrickCompleted>,
+    mut combo_events: EventReader<ComboEnded>,
+    mut bailed_events: EventReader<TrickBailed>,
+    mut popup_query: Query<(&mut TrickPopup, &mut Text)>,
+    player_query: Query<&PlayerNumber, With<Player>>,
+) {
+    let label = |player: Entity| {
+        player_query
+            .get(player)
+            .map(|number| player_label(number, player_query.iter().len()))
+            .unwrap_or_default()
+    };
+    // the trick's name shows while it's being done, then how it ended
+    let started = started_events
+        .read()
+        .map(|started| (format!("{}{}", label(started.player), started.name), Color::WHITE));
+    let completed = completed_events.read().map(|completed| {
+        (
+            format!("{}{} +{}", label(completed.player), completed.name, completed.points),
+            Color::GOLD,
+        )
+    });
+    let combos = combo_events
+        .read()
+        .filter(|combo| combo.points > 0 && combo.tricks > 1)
+        .map(|combo| {
+            (
+                format!("{}combo x{} +{}", label(combo.player), combo.tricks, combo.points),
+                Color::GOLD,
+            )
+        });
+    let bails = bailed_events
+        .read()
+        .map(|bailed| (format!("{}bailed {}", label(bailed.player), bailed.name), Color::RED));
+    let newest = started.chain(completed).chain(combos).chain(bails).last();
+    popup_query.iter_mut().for_each(|(mut popup, mut text)| {
+        if let Some((value, color)) = newest.clone() {
+            text.sections[0].value = value;
+            text.sections[0].style.color = color;
+            popup.0.reset();
+        }
+        popup.0.tick(time.delta());
+        let color = text.sections[0].style.color;
+        text.sections[0].style.color = color.with_a(1. - popup.0.percent());
+    });
+}
+
+// One line of health and lives per player
+fn update_health_gui(
+    mut text_query: Query<&mut Text, With<HealthText>>,
+    player_query: Query<(&PlayerNumber, &Health, &Lives), With<Player>>,
+) {
+    let mut players = player_query.iter().collect::<Vec<_>>();
+    if players.is_empty() {
+        return;
+    }
+    players.sort_by_key(|(number, _, _)| number.0);
+    text_query.iter_mut().for_each(|mut text| {
+        let health_style = text.sections[0].style.clone();
+        let lives_style = text.sections[1].style.clone();
+        text.sections = players
+            .iter()
+            .enumerate()
+            .flat_map(|(line, (number, health, lives))| {
+                let new_line = if line == 0 { "" } else { "\n" };
+                [
+                    TextSection::new(
+                        format!(
+                            "{}{}Health: {}/{}",
+                            new_line,
+                            player_label(number, players.len()),
+                            health.current,
+                            health.max
+                        ),
+                        health_style.clone(),
+                    ),
+                    TextSection::new(format!("  Lives: {}", lives.0), lives_style.clone()),
+                ]
+            })
+            .collect();
     });
 }
 
@@ -301,7 +653,11 @@ impl Plugin for LevelScene {
     fn build(&self, app: &mut App) {
         app.add_state::<LevelState>();
         app.insert_resource(LevelStopwatch(Stopwatch::new()));
-        app.add_systems(OnEnter(Scene::Level), initialize_gui);
+        app.init_resource::<PerformedTricks>();
+        app.add_systems(
+            OnEnter(Scene::Level),
+            (initialize_gui, reset_level_stopwatch, reset_performed_tricks),
+        );
         app.insert_resource(LevelResult {
             level_id: "".to_string(),
             score: 0,
@@ -324,27 +680,62 @@ impl Plugin for LevelScene {
             PlayerManager {
                 scene: Scene::Level,
             },
+            HealthManager {
+                scene: Scene::Level,
+            },
+            ReplayManager {
+                scene: Scene::Level,
+            },
+            GhostManager {
+                scene: Scene::Level,
+            },
         ));
         app.add_systems(
             Update,
-            (update_level, update_gui).run_if(in_state(Scene::Level)),
+            (
+                update_level,
+                update_gui,
+                update_health_gui,
+                update_trick_popup,
+                record_performed_tricks,
+            )
+                .run_if(in_state(Scene::Level)),
         );
         app.add_systems(
-            Update,
-            (hit_checkmark)
+            FixedUpdate,
+            (
+                tick_level_stopwatch,
+                hit_checkmark,
+                pick_up_collectibles,
+                fail_level,
+            )
+                .chain()
+                .in_set(GameplaySet)
+                // after everything that can still change the score this step
+                .after(update_player_state)
                 .run_if(in_state(Scene::Level))
                 .run_if(in_state(LevelState::PrePlay)),
         );
 
         app.add_systems(
             OnEnter(LevelState::Over),
-            (handle_enter_post_game, create_post_game_gui).chain(),
+            (
+                handle_enter_post_game,
+                create_post_game_gui,
+                finish_replay_on_completed,
+                save_ghost_on_completed,
+            )
+                .chain(),
+        );
+        app.add_systems(
+            OnEnter(LevelState::Failed),
+            (handle_enter_failed, create_failed_gui, finish_replay_on_failed),
         );
         app.add_systems(
             Update,
             (handle_post_game_update)
                 .run_if(in_state(Scene::Level))
-                .run_if(in_state(LevelState::Over)),
+                .run_if(in_state(LevelState::Over).or_else(in_state(LevelState::Failed))),
         );
         app.add_systems(OnExit(Scene::Level), cleanup);
     }
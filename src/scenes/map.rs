@@ -1,73 +1,289 @@
 use bevy::{
     app::{App, Plugin, Update},
+    asset::AssetServer,
     core_pipeline::core_2d::Camera2dBundle,
     ecs::{
-        schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit},
-        system::{Commands, Res, ResMut},
+        component::Component,
+        entity::Entity,
+        query::{Changed, With},
+        schedule::{
+            common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit, State,
+        },
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    hierarchy::BuildChildren,
+    input::Input,
+    render::color::Color,
+    text::{Text, TextStyle},
+    ui::{
+        node_bundles::{ButtonBundle, NodeBundle, TextBundle},
+        AlignItems, BackgroundColor, FlexDirection, Interaction, JustifyContent, Style, UiRect,
+        Val,
     },
-    input::{keyboard::KeyCode, Input},
     utils::default,
 };
 
-use crate::scenes::level::LevelID;
+use crate::{
+    models::BelongsToScene,
+    plugins::delete_manager::DeleteMe,
+    scenes::level::LevelID,
+    service::{
+        bindings::{Action, Bindings},
+        campaign::{Campaign, CampaignLevel},
+        user_stats::{self, UserStats},
+    },
+};
 
 use super::Scene;
 
-fn setup_map() {
-    println!("setup_map");
+const SELECTED_COLOR: Color = Color::rgb(0.35, 0.3, 0.1);
+const UNSELECTED_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+
+// A level along with what's left to do before it can be played
+struct LevelOption {
+    level: CampaignLevel,
+    missing_requirements: Vec<String>,
+}
+
+#[derive(Resource)]
+struct LevelSelect {
+    levels: Vec<LevelOption>,
+    selected: usize,
+    // race against the ghost of the best run of each level
+    show_ghosts: bool,
+}
+
+#[derive(Component)]
+struct LevelButton(usize);
+
+#[derive(Component)]
+struct GhostToggleText;
+
+fn ghost_toggle_label(show_ghosts: bool, bindings: &Bindings) -> String {
+    let key = bindings
+        .keys_for(Action::ToggleGhost)
+        .first()
+        .map(|key| format!("{:?}", key))
+        .unwrap_or_else(|| "unbound".to_string());
+    let state = if show_ghosts { "on" } else { "off" };
+    format!("[{}] best run ghost: {}", key, state)
+}
+
+// "MM:SS" from a time in ms
+fn format_time(ms: usize) -> String {
+    let seconds = ms / 1000;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
 }
-fn update_home(
+
+// every ui node is tagged with the scene since DeleteMe doesn't despawn children
+fn setup_map(mut commands: Commands, asset_server: Res<AssetServer>, bindings: Res<Bindings>) {
+    let campaign = Campaign::read_from_file();
+    let user_stats = UserStats::load_from_file().unwrap_or_default();
+    let show_ghosts = !user_stats.hide_ghosts;
+    let mut options = vec![];
+    let font = asset_server.load("PixelifySans-VariableFont_wght.ttf");
+    let text_style = |color: Color| TextStyle {
+        font: font.clone(),
+        font_size: 30.0,
+        color,
+    };
+
+    commands.spawn((Camera2dBundle { ..default() }, BelongsToScene(Scene::Map)));
+    commands
+        .spawn((
+            BelongsToScene(Scene::Map),
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BelongsToScene(Scene::Map),
+                TextBundle::from_section(
+                    "Select a level",
+                    TextStyle {
+                        font_size: 60.0,
+                        ..text_style(Color::GOLD)
+                    },
+                ),
+            ));
+            parent.spawn((
+                BelongsToScene(Scene::Map),
+                GhostToggleText,
+                TextBundle::from_section(
+                    ghost_toggle_label(show_ghosts, &bindings),
+                    text_style(Color::GRAY),
+                ),
+            ));
+            let mut previous_world = None;
+            campaign
+                .levels()
+                .enumerate()
+                .for_each(|(index, (world, level))| {
+                    if previous_world != Some(&world.name) {
+                        previous_world = Some(&world.name);
+                        parent.spawn((
+                            BelongsToScene(Scene::Map),
+                            TextBundle::from_section(world.name.clone(), text_style(Color::GOLD)),
+                        ));
+                    }
+                    let missing_requirements =
+                        campaign.missing_requirements(world, level, &user_stats);
+                    let locked = !missing_requirements.is_empty();
+                    let stats = if locked {
+                        format!("locked: {}", missing_requirements.join(", "))
+                    } else {
+                        format!(
+                            "best time {}  best score {}",
+                            user_stats
+                                .best_time(&level.id)
+                                .map(format_time)
+                                .unwrap_or_else(|| "--:--".to_string()),
+                            user_stats
+                                .best_score(&level.id)
+                                .map(|score| score.to_string())
+                                .unwrap_or_else(|| "-".to_string()),
+                        )
+                    };
+                    let color = if locked { Color::GRAY } else { Color::WHITE };
+                    parent
+                        .spawn((
+                            BelongsToScene(Scene::Map),
+                            LevelButton(index),
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(1000.),
+                                    padding: UiRect::all(Val::Px(10.)),
+                                    justify_content: JustifyContent::SpaceBetween,
+                                    ..default()
+                                },
+                                background_color: UNSELECTED_COLOR.into(),
+                                ..default()
+                            },
+                        ))
+                        .with_children(|button| {
+                            button.spawn((
+                                BelongsToScene(Scene::Map),
+                                TextBundle::from_section(level.name.clone(), text_style(color)),
+                            ));
+                            button.spawn((
+                                BelongsToScene(Scene::Map),
+                                TextBundle::from_section(stats, text_style(color)),
+                            ));
+                        });
+                    options.push(LevelOption {
+                        level: level.clone(),
+                        missing_requirements,
+                    });
+                });
+        });
+    // start on the furthest level the player can play
+    let selected = options
+        .iter()
+        .rposition(|option| option.missing_requirements.is_empty())
+        .unwrap_or(0);
+    commands.insert_resource(LevelSelect {
+        levels: options,
+        selected,
+        show_ghosts,
+    });
+}
+
+fn update_level_select(
     mut commands: Commands,
-    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
+    bindings: Res<Bindings>,
+    mut level_select: ResMut<LevelSelect>,
+    button_query: Query<(&Interaction, &LevelButton), Changed<Interaction>>,
+    mut ghost_text_query: Query<&mut Text, With<GhostToggleText>>,
     mut scene_state: ResMut<NextState<Scene>>,
 ) {
-    let mut should_nav = false;
-    // if keyboard input number 1 is pressed
-    // set the resource LevelID to "plains_1"
-    if keyboard_input.just_pressed(KeyCode::Key1) {
-        println!("LevelID set to plains_1");
-        commands.insert_resource(LevelID("untitled_old.tmx".to_string()));
-        should_nav = true;
+    if actions.just_pressed(Action::Back) {
+        scene_state.set(Scene::Home);
+        return;
     }
-    // if keyboard input number 2 is pressed
-    // set the resource LevelID to "plains_2"
-    if keyboard_input.just_pressed(KeyCode::Key2) {
-        println!("LevelID set to plains_2");
-        commands.insert_resource(LevelID("plains_2.tmx".to_string()));
-        should_nav = true;
+    if actions.just_pressed(Action::ToggleGhost) {
+        level_select.show_ghosts = !level_select.show_ghosts;
+        user_stats::record_hide_ghosts(!level_select.show_ghosts);
+        ghost_text_query.iter_mut().for_each(|mut text| {
+            text.sections[0].value = ghost_toggle_label(level_select.show_ghosts, &bindings);
+        });
     }
-    // if keyboard input number 3 is pressed
-    // set the resource LevelID to "plains_3"
-    if keyboard_input.just_pressed(KeyCode::Key3) {
-        println!("LevelID set to plains_3");
-        commands.insert_resource(LevelID("jumping.tmx".to_string()));
-        should_nav = true;
+    if level_select.levels.is_empty() {
+        return;
     }
-    // if keyboard input number 4 is pressed
-    // set the resource LevelID to "plains_4"
-    if keyboard_input.just_pressed(KeyCode::Key4) {
-        println!("LevelID set to plains_4");
-        commands.insert_resource(LevelID("long.tmx".to_string()));
-        should_nav = true;
+    let count = level_select.levels.len();
+    if actions.just_pressed(Action::MenuDown) {
+        level_select.selected = (level_select.selected + 1) % count;
     }
-    // if keyboard input number 5 is pressed
-    // set the resource LevelID to "plains_5"
-    if keyboard_input.just_pressed(KeyCode::Key5) {
-        println!("LevelID set to plains_5");
-        commands.insert_resource(LevelID("plains_5".to_string()));
-        should_nav = true;
+    if actions.just_pressed(Action::MenuUp) {
+        level_select.selected = (level_select.selected + count - 1) % count;
     }
-    if should_nav {
-        scene_state.set(Scene::Level);
+    let mut should_nav = actions.just_pressed(Action::Confirm);
+    button_query
+        .iter()
+        .for_each(|(interaction, button)| match interaction {
+            Interaction::Hovered => level_select.selected = button.0,
+            Interaction::Pressed => {
+                level_select.selected = button.0;
+                should_nav = true;
+            }
+            Interaction::None => {}
+        });
+    let option = &level_select.levels[level_select.selected];
+    if should_nav && option.missing_requirements.is_empty() {
+        commands.insert_resource(LevelID(option.level.id.clone()));
+        scene_state.set(Scene::CharacterSelect);
     }
 }
-fn exit_home() {}
+
+fn highlight_selected(
+    level_select: Res<LevelSelect>,
+    mut button_query: Query<(&LevelButton, &mut BackgroundColor)>,
+) {
+    button_query.iter_mut().for_each(|(button, mut color)| {
+        *color = if button.0 == level_select.selected {
+            SELECTED_COLOR.into()
+        } else {
+            UNSELECTED_COLOR.into()
+        };
+    });
+}
+
+fn exit_map(
+    mut commands: Commands,
+    belongs_to_scene_query: Query<(Entity, &BelongsToScene)>,
+    current_scene: Res<State<Scene>>,
+) {
+    belongs_to_scene_query
+        .iter()
+        .for_each(|(entity, owned_by_scene)| {
+            if owned_by_scene.0 != **current_scene {
+                commands.entity(entity).insert(DeleteMe {});
+            }
+        });
+    commands.remove_resource::<LevelSelect>();
+}
 
 pub struct MapScene;
 impl Plugin for MapScene {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(Scene::Map), setup_map);
-        app.add_systems(Update, (update_home).run_if(in_state(Scene::Map)));
-        app.add_systems(OnExit(Scene::Map), exit_home);
+        app.add_systems(
+            Update,
+            (update_level_select, highlight_selected)
+                .chain()
+                .run_if(in_state(Scene::Map)),
+        );
+        app.add_systems(OnExit(Scene::Map), exit_map);
     }
 }
@@ -1,8 +1,14 @@
 use bevy::{app::Plugin, ecs::schedule::States};
 use serde::{Deserialize, Serialize};
 
-use self::{home::HomeScene, level::LevelScene, map::MapScene};
+use self::{
+    character_select::CharacterSelectScene, controls::ControlsScene, editor::EditorScene,
+    home::HomeScene, level::LevelScene, map::MapScene,
+};
 
+pub mod character_select;
+pub mod controls;
+pub mod editor;
 pub mod home;
 pub mod level;
 pub mod map;
@@ -13,6 +19,8 @@ pub enum Scene {
     #[default]
     Home,
     Level,
+    Controls,
+    CharacterSelect,
 }
 
 pub struct SceneManager;
@@ -22,6 +30,9 @@ impl Plugin for SceneManager {
         app.add_plugins(HomeScene);
         app.add_plugins(LevelScene);
         app.add_plugins(MapScene);
+        app.add_plugins(EditorScene);
+        app.add_plugins(ControlsScene);
+        app.add_plugins(CharacterSelectScene);
     }
 }
 
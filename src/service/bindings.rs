@@ -0,0 +1,192 @@
+use bevy::{
+    ecs::system::Resource,
+    input::{gamepad::GamepadButtonType, keyboard::KeyCode},
+};
+use serde::{Deserialize, Serialize};
+
+use super::constants::Constants;
+
+// Everything the player can do, systems read these instead of raw keys
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Dash,
+    Crouch,
+    // picks the dash direction along with the move actions
+    AimUp,
+    AimDown,
+    TrickUp,
+    TrickDown,
+    TrickLeft,
+    TrickRight,
+    Pause,
+    Confirm,
+    Back,
+    MenuUp,
+    MenuDown,
+    Settings,
+    // shows or hides the personal best ghost from the level select
+    ToggleGhost,
+    // a gamepad nobody is playing on adds another player mid level
+    Join,
+    // opens the running level up in the editor, and goes back to playing it
+    ToggleEditor,
+}
+
+impl Action {
+    pub const ALL: [Action; 20] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Dash,
+        Action::Crouch,
+        Action::AimUp,
+        Action::AimDown,
+        Action::TrickUp,
+        Action::TrickDown,
+        Action::TrickLeft,
+        Action::TrickRight,
+        Action::Pause,
+        Action::Confirm,
+        Action::Back,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::Settings,
+        Action::ToggleGhost,
+        Action::Join,
+        Action::ToggleEditor,
+    ];
+}
+
+// Which keys and gamepad buttons trigger which action, saved next to the user stats.
+// Tricks get their own keys and the d-pad to themselves, a trick is done while
+// still moving and aiming so sharing them would set one off on every step
+#[derive(Serialize, Deserialize, Debug, Clone, Resource)]
+pub struct Bindings {
+    pub keys: Vec<(Action, Vec<KeyCode>)>,
+    #[serde(default = "default_buttons")]
+    pub buttons: Vec<(Action, Vec<GamepadButtonType>)>,
+}
+
+fn default_buttons() -> Vec<(Action, Vec<GamepadButtonType>)> {
+    vec![
+        // moving, aiming and crouching come from the left stick
+        (Action::MoveLeft, vec![]),
+        (Action::MoveRight, vec![]),
+        (Action::Jump, vec![GamepadButtonType::South]),
+        (Action::Dash, vec![GamepadButtonType::West]),
+        (Action::Crouch, vec![]),
+        (Action::AimUp, vec![]),
+        (Action::AimDown, vec![]),
+        (Action::TrickUp, vec![GamepadButtonType::DPadUp]),
+        (Action::TrickDown, vec![GamepadButtonType::DPadDown]),
+        (Action::TrickLeft, vec![GamepadButtonType::DPadLeft]),
+        (Action::TrickRight, vec![GamepadButtonType::DPadRight]),
+        (Action::Pause, vec![GamepadButtonType::Start]),
+        (Action::Confirm, vec![GamepadButtonType::South]),
+        (Action::Back, vec![GamepadButtonType::East]),
+        (Action::MenuUp, vec![GamepadButtonType::DPadUp]),
+        (Action::MenuDown, vec![GamepadButtonType::DPadDown]),
+        (Action::Settings, vec![GamepadButtonType::Select]),
+        (Action::ToggleGhost, vec![GamepadButtonType::North]),
+        (Action::Join, vec![GamepadButtonType::RightTrigger]),
+        (Action::ToggleEditor, vec![]),
+    ]
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            keys: vec![
+                (Action::MoveLeft, vec![KeyCode::A, KeyCode::Left]),
+                (Action::MoveRight, vec![KeyCode::D, KeyCode::Right]),
+                (Action::Jump, vec![KeyCode::Space]),
+                (Action::Dash, vec![KeyCode::ShiftLeft]),
+                (Action::Crouch, vec![KeyCode::S, KeyCode::Down]),
+                (Action::AimUp, vec![KeyCode::W]),
+                (Action::AimDown, vec![KeyCode::S]),
+                (Action::TrickUp, vec![KeyCode::I]),
+                (Action::TrickDown, vec![KeyCode::K]),
+                (Action::TrickLeft, vec![KeyCode::J]),
+                (Action::TrickRight, vec![KeyCode::L]),
+                (Action::Pause, vec![KeyCode::Escape]),
+                (Action::Confirm, vec![KeyCode::Return, KeyCode::Space]),
+                (Action::Back, vec![KeyCode::Escape]),
+                (Action::MenuUp, vec![KeyCode::W, KeyCode::Up]),
+                (Action::MenuDown, vec![KeyCode::S, KeyCode::Down]),
+                (Action::Settings, vec![KeyCode::C]),
+                (Action::ToggleGhost, vec![KeyCode::G]),
+                (Action::Join, vec![]),
+                (Action::ToggleEditor, vec![KeyCode::F2]),
+            ],
+            buttons: default_buttons(),
+        }
+    }
+}
+
+// Keeps the saved file in the same order as Action::ALL
+fn action_order(action: &Action) -> usize {
+    Action::ALL
+        .iter()
+        .position(|candidate| candidate == action)
+        .unwrap_or(0)
+}
+
+impl Bindings {
+    fn file_path(constants: &Constants) -> String {
+        format!("{}/bindings.json", constants.path_to_player_data)
+    }
+
+    pub fn load_from_file(constants: &Constants) -> Option<Bindings> {
+        let raw = std::fs::read_to_string(Bindings::file_path(constants)).ok()?;
+        let mut bindings = serde_json::from_str::<Bindings>(&raw).ok()?;
+        // actions added after the file was written get their default keys
+        let defaults = Bindings::default();
+        defaults.keys.into_iter().for_each(|(action, keys)| {
+            if !bindings.keys.iter().any(|(bound, _)| *bound == action) {
+                bindings.keys.push((action, keys));
+            }
+        });
+        defaults.buttons.into_iter().for_each(|(action, buttons)| {
+            if !bindings.buttons.iter().any(|(bound, _)| *bound == action) {
+                bindings.buttons.push((action, buttons));
+            }
+        });
+        Some(bindings)
+    }
+
+    pub fn save_to_file(&self, constants: &Constants) {
+        let serialized = serde_json::to_string_pretty(&self).unwrap();
+        std::fs::write(Bindings::file_path(constants), serialized).unwrap();
+    }
+
+    pub fn keys_for(&self, action: Action) -> Vec<KeyCode> {
+        self.keys
+            .iter()
+            .filter(|(bound, _)| *bound == action)
+            .flat_map(|(_, keys)| keys.iter().cloned())
+            .collect()
+    }
+
+    pub fn buttons_for(&self, action: Action) -> Vec<GamepadButtonType> {
+        self.buttons
+            .iter()
+            .filter(|(bound, _)| *bound == action)
+            .flat_map(|(_, buttons)| buttons.iter().cloned())
+            .collect()
+    }
+
+    pub fn set_keys(&mut self, action: Action, keys: Vec<KeyCode>) {
+        self.keys.retain(|(bound, _)| *bound != action);
+        self.keys.push((action, keys));
+        self.keys.sort_by_key(|(bound, _)| action_order(bound));
+    }
+
+    pub fn set_buttons(&mut self, action: Action, buttons: Vec<GamepadButtonType>) {
+        self.buttons.retain(|(bound, _)| *bound != action);
+        self.buttons.push((action, buttons));
+        self.buttons.sort_by_key(|(bound, _)| action_order(bound));
+    }
+}
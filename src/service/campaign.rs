@@ -0,0 +1,396 @@
+use bevy::log::warn;
+use serde::{Deserialize, Serialize};
+use tiled::Loader;
+
+use super::user_stats::UserStats;
+
+// Something the player has to have done before a level or world opens up
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum UnlockCondition {
+    // beat the level that comes right before this one in the campaign
+    FinishPrevious,
+    // beat a specific level, by map file name
+    Finish(String),
+    Score { level: String, at_least: usize },
+    // pick up every gem in every level of the world with this name
+    CollectAllGems(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CampaignLevel {
+    // file name of the map in the assets folder
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub unlock: Vec<UnlockCondition>,
+    // counted from the map when the campaign is loaded
+    #[serde(skip)]
+    pub gems: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct World {
+    pub name: String,
+    // applies to every level in the world on top of their own conditions
+    #[serde(default)]
+    pub unlock: Vec<UnlockCondition>,
+    pub levels: Vec<CampaignLevel>,
+}
+
+// Every level in the game, grouped into worlds and in the order they are played
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Campaign {
+    pub worlds: Vec<World>,
+}
+
+// Same rule the level analysis uses, any object with a collectible property
+fn count_gems(level_id: &str) -> usize {
+    Loader::new()
+        .load_tmx_map(format!("./assets/{}", level_id))
+        .map(|map| {
+            map.layers()
+                .filter_map(|layer| layer.as_object_layer())
+                .map(|object_layer| {
+                    object_layer
+                        .objects()
+                        .filter(|object| object.properties.contains_key("collectible"))
+                        .count()
+                })
+                .sum::<usize>()
+        })
+        .unwrap_or(0)
+}
+
+impl Campaign {
+    pub fn read_from_file() -> Campaign {
+        let raw = std::fs::read_to_string("./assets/campaign.json").unwrap();
+        let mut campaign = serde_json::from_str::<Campaign>(&raw).unwrap();
+        campaign.worlds.iter_mut().for_each(|world| {
+            // a typo in the campaign shouldn't crash the game once the level is picked
+            world.levels.retain(|level| {
+                let exists = std::path::Path::new(&format!("./assets/{}", level.id)).exists();
+                if !exists {
+                    warn!("level {} is in the campaign but has no map file", level.id);
+                }
+                exists
+            });
+            world
+                .levels
+                .iter_mut()
+                .for_each(|level| level.gems = count_gems(&level.id));
+        });
+        campaign
+    }
+
+    pub fn levels(&self) -> impl Iterator<Item = (&World, &CampaignLevel)> {
+        self.worlds
+            .iter()
+            .flat_map(|world| world.levels.iter().map(move |level| (world, level)))
+    }
+
+    fn level_name(&self, level_id: &str) -> String {
+        self.levels()
+            .find(|(_, level)| level.id == level_id)
+            .map(|(_, level)| level.name.clone())
+            .unwrap_or_else(|| level_id.to_string())
+    }
+
+    fn previous_level(&self, level_id: &str) -> Option<&CampaignLevel> {
+        let levels = self.levels().map(|(_, level)| level).collect::<Vec<_>>();
+        levels
+            .iter()
+            .position(|level| level.id == level_id)
+            .and_then(|index| index.checked_sub(1))
+            .map(|index| levels[index])
+    }
+
+    pub fn is_met(&self, condition: &UnlockCondition, level_id: &str, stats: &UserStats) -> bool {
+        match condition {
+            UnlockCondition::FinishPrevious => self
+                .previous_level(level_id)
+                .map(|previous| stats.has_finished(&previous.id))
+                .unwrap_or(true),
+            UnlockCondition::Finish(level) => stats.has_finished(level),
+            UnlockCondition::Score { level, at_least } => {
+                stats.best_score(level).unwrap_or(0) >= *at_least
+            }
+            UnlockCondition::CollectAllGems(world) => self
+                .worlds
+                .iter()
+                .filter(|candidate| candidate.name == *world)
+                .flat_map(|world| world.levels.iter())
+                .all(|level| stats.gems_collected(&level.id) >= level.gems),
+        }
+    }
+
+    pub fn describe(&self, condition: &UnlockCondition, level_id: &str) -> String {
+        match condition {
+            UnlockCondition::FinishPrevious => format!(
+                "finish {}",
+                self.previous_level(level_id)
+                    .map(|previous| previous.name.clone())
+                    .unwrap_or_default()
+            ),
+            UnlockCondition::Finish(level) => format!("finish {}", self.level_name(level)),
+            UnlockCondition::Score { level, at_least } => {
+                format!("score {} on {}", at_least, self.level_name(level))
+            }
+            UnlockCondition::CollectAllGems(world) => format!("collect all gems in {}", world),
+        }
+    }
+
+    // What the player still has to do before they can play the level,
+    // empty when it is unlocked
+    pub fn missing_requirements(
+        &self,
+        world: &World,
+        level: &CampaignLevel,
+        stats: &UserStats,
+    ) -> Vec<String> {
+        // world conditions are about getting into the world, so they are
+        // checked from its first level
+        let first_level = world
+            .levels
+            .first()
+            .map(|first| first.id.as_str())
+            .unwrap_or(&level.id);
+        world
+            .unlock
+            .iter()
+            .map(|condition| (condition, first_level))
+            .chain(
+                level
+                    .unlock
+                    .iter()
+                    .map(|condition| (condition, level.id.as_str())),
+            )
+            .filter(|(condition, level_id)| !self.is_met(condition, level_id, stats))
+            .map(|(condition, level_id)| self.describe(condition, level_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::user_stats::{CollectedGems, LevelResult};
+
+    fn level(id: &str, gems: usize, unlock: Vec<UnlockCondition>) -> CampaignLevel {
+        CampaignLevel {
+            id: id.to_string(),
+            name: id.to_string(),
+            unlock,
+            gems,
+        }
+    }
+
+    fn campaign() -> Campaign {
+        Campaign {
+            worlds: vec![
+                World {
+                    name: "Plains".to_string(),
+                    unlock: vec![],
+                    levels: vec![
+                        level("first", 2, vec![UnlockCondition::FinishPrevious]),
+                        level("second", 1, vec![UnlockCondition::FinishPrevious]),
+                    ],
+                },
+                World {
+                    name: "Hills".to_string(),
+                    unlock: vec![UnlockCondition::CollectAllGems("Plains".to_string())],
+                    levels: vec![
+                        level("third", 0, vec![UnlockCondition::FinishPrevious]),
+                        level(
+                            "fourth",
+                            0,
+                            vec![UnlockCondition::Score {
+                                level: "second".to_string(),
+                                at_least: 500,
+                            }],
+                        ),
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn result(level_id: &str, score: usize) -> LevelResult {
+        LevelResult {
+            level_id: level_id.to_string(),
+            time: 1000,
+            score,
+        }
+    }
+
+    fn gems(level_id: &str, gem_ids: &[u32]) -> CollectedGems {
+        CollectedGems {
+            level_id: level_id.to_string(),
+            gem_ids: gem_ids.to_vec(),
+        }
+    }
+
+    // the first level is finished with every gem, the second with a score
+    // of 500 and one of its gems
+    fn stats() -> UserStats {
+        UserStats {
+            level_results_points: vec![result("first", 200), result("second", 500)],
+            level_results_time: vec![result("first", 200), result("second", 500)],
+            collected_gems: vec![gems("first", &[1, 2]), gems("second", &[3])],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn conditions_are_checked_against_the_stats() {
+        let campaign = campaign();
+        let fresh = UserStats::default();
+        let score = |at_least| UnlockCondition::Score {
+            level: "second".to_string(),
+            at_least,
+        };
+        let cases = [
+            (
+                "nothing comes before the first level",
+                UnlockCondition::FinishPrevious,
+                "first",
+                &fresh,
+                true,
+            ),
+            (
+                "the previous level isn't finished",
+                UnlockCondition::FinishPrevious,
+                "second",
+                &fresh,
+                false,
+            ),
+            (
+                "the previous level is finished",
+                UnlockCondition::FinishPrevious,
+                "second",
+                &stats(),
+                true,
+            ),
+            (
+                "previous crosses into the next world",
+                UnlockCondition::FinishPrevious,
+                "third",
+                &stats(),
+                true,
+            ),
+            (
+                "unknown levels have nothing before them",
+                UnlockCondition::FinishPrevious,
+                "missing",
+                &fresh,
+                true,
+            ),
+            (
+                "finishing an unknown level",
+                UnlockCondition::Finish("missing".to_string()),
+                "first",
+                &stats(),
+                false,
+            ),
+            (
+                "score below the threshold",
+                score(501),
+                "fourth",
+                &stats(),
+                false,
+            ),
+            (
+                "score right on the threshold",
+                score(500),
+                "fourth",
+                &stats(),
+                true,
+            ),
+            (
+                "score on a level never played",
+                score(1),
+                "fourth",
+                &fresh,
+                false,
+            ),
+            ("no score needed", score(0), "fourth", &fresh, true),
+            (
+                "every gem in the world",
+                UnlockCondition::CollectAllGems("Plains".to_string()),
+                "third",
+                &stats(),
+                true,
+            ),
+            (
+                "no gems picked up",
+                UnlockCondition::CollectAllGems("Plains".to_string()),
+                "third",
+                &fresh,
+                false,
+            ),
+            (
+                "unknown worlds have no gems",
+                UnlockCondition::CollectAllGems("Caves".to_string()),
+                "third",
+                &fresh,
+                true,
+            ),
+        ];
+        cases
+            .into_iter()
+            .for_each(|(case, condition, level_id, stats, met)| {
+                assert_eq!(
+                    campaign.is_met(&condition, level_id, stats),
+                    met,
+                    "{}",
+                    case
+                );
+            });
+    }
+
+    #[test]
+    fn missing_requirements_include_the_worlds_conditions() {
+        let campaign = campaign();
+        let mut partial = stats();
+        partial.collected_gems = vec![gems("first", &[1])];
+        let hills = &campaign.worlds[1];
+        let cases = [
+            ("first", &campaign.worlds[0], UserStats::default(), vec![]),
+            (
+                "second",
+                &campaign.worlds[0],
+                UserStats::default(),
+                vec!["finish first"],
+            ),
+            ("second", &campaign.worlds[0], stats(), vec![]),
+            (
+                "third",
+                hills,
+                partial.clone(),
+                vec!["collect all gems in Plains"],
+            ),
+            ("third", hills, stats(), vec![]),
+            (
+                "fourth",
+                hills,
+                UserStats::default(),
+                vec!["collect all gems in Plains", "score 500 on second"],
+            ),
+            ("fourth", hills, partial, vec!["collect all gems in Plains"]),
+        ];
+        cases
+            .into_iter()
+            .for_each(|(level_id, world, stats, missing)| {
+                let level = world
+                    .levels
+                    .iter()
+                    .find(|level| level.id == level_id)
+                    .unwrap();
+                assert_eq!(
+                    campaign.missing_requirements(world, level, &stats),
+                    missing,
+                    "{}",
+                    level_id
+                );
+            });
+    }
+}
@@ -9,20 +9,48 @@ use serde::{Deserialize, Serialize};
 pub struct Constants {
     pub post_level_secs: u64,
     pub map_name: String,
-    pub dash_force: f32,
     pub trick_time: f32,
+    pub combo_multiplier_step: f32,
+    pub combo_repeat_falloff: f32,
+    pub combo_land_time: f32,
     pub squish_bounce_force: f32,
+    pub stomp_points: u32,
     pub character_sheet: String,
     pub player_speed: f32,
     pub max_player_speed: f32,
     pub jump_force: f32,
     pub initial_jump_time: f32,
+    pub coyote_time: f32,
+    pub jump_buffer_time: f32,
     pub gravity: f32,
     pub curve_pow: f32,
     pub grounded_decay: f32,
     pub grounded_threshold: f32,
-    pub wall_threshold: f32,
+    pub max_slope_angle: f32,
+    pub wall_slide_speed: f32,
+    pub wall_jump_force_x: f32,
+    pub wall_jump_force_y: f32,
+    pub wall_jump_lock_time: f32,
+    pub dash_speed: f32,
+    pub dash_time: f32,
+    pub dash_cooldown: f32,
+    pub air_dashes: u32,
+    pub crouch_height: f32,
+    pub crouch_speed: f32,
+    pub slide_min_speed: f32,
+    pub slide_friction: f32,
+    pub ledge_grab_reach: f32,
+    pub ledge_climb_time: f32,
+    pub ledge_regrab_time: f32,
+    pub player_health: u32,
+    pub player_lives: u32,
+    pub invulnerable_time: f32,
+    pub knockback_force_x: f32,
+    pub knockback_force_y: f32,
+    pub respawn_time: f32,
     pub path_to_player_data: String,
+    pub gamepad_deadzone: f32,
+    pub gamepad_flick_threshold: f32,
 }
 
 impl Constants {
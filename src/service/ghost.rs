@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use super::constants::Constants;
+
+// Where a player was and what they looked like on one fixed step
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GhostFrame {
+    pub x: f32,
+    pub y: f32,
+    pub sprite: usize,
+    pub flip_x: bool,
+}
+
+// One player's path through the level, one frame for every fixed step from the
+// step they joined on
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GhostPath {
+    pub start_step: u64,
+    pub frames: Vec<GhostFrame>,
+}
+
+impl GhostPath {
+    pub fn frame_at(&self, step: u64) -> Option<GhostFrame> {
+        let offset = step.checked_sub(self.start_step)?;
+        self.frames.get(offset as usize).copied()
+    }
+}
+
+// The fastest finish of a level, kept so it can be raced against
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GhostRun {
+    pub level_id: String,
+    // ms, the same as LevelResult::time
+    pub time: usize,
+    // one path per player, in player order
+    pub players: Vec<GhostPath>,
+}
+
+impl GhostRun {
+    fn file_path(constants: &Constants, level_id: &str) -> String {
+        format!(
+            "{}/ghosts/{}.json",
+            constants.path_to_player_data,
+            level_id.trim_end_matches(".tmx")
+        )
+    }
+
+    pub fn load(constants: &Constants, level_id: &str) -> Option<GhostRun> {
+        let raw = std::fs::read_to_string(GhostRun::file_path(constants, level_id)).ok()?;
+        serde_json::from_str::<GhostRun>(&raw).ok()
+    }
+
+    // Only replaces the saved ghost when this run was faster
+    pub fn save_if_best(&self, constants: &Constants) -> bool {
+        let best = GhostRun::load(constants, &self.level_id);
+        if best.is_some_and(|best| best.time <= self.time) {
+            return false;
+        }
+        std::fs::create_dir_all(format!("{}/ghosts", constants.path_to_player_data)).unwrap();
+        std::fs::write(
+            GhostRun::file_path(constants, &self.level_id),
+            serde_json::to_string(&self).unwrap(),
+        )
+        .unwrap();
+        true
+    }
+}
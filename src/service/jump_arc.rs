@@ -0,0 +1,100 @@
+use crate::{models::StatModifiers, plugins::physics::FIXED_HZ};
+
+use super::constants::Constants;
+
+// Step used when simulating the jump, one tick of the fixed physics update
+const SIMULATION_STEP: f32 = 1. / FIXED_HZ as f32;
+// Upper bound on how long we simulate a jump for, so that odd constants
+// (like zero gravity) can't loop forever
+pub const MAX_SIMULATION_SECS: f32 = 10.;
+
+// Approximation of the height of a jump from flat ground over time.
+// The same forces player_manager::update_velocity_with_input applies each
+// fixed step, so that tools (level generation, analysis) can reason about what
+// a character is actually able to reach with the current constants.
+#[derive(Debug, Clone)]
+pub struct JumpArc {
+    pub step: f32,
+    // height above the take off point for every simulated step
+    pub heights: Vec<f32>,
+    // vertical velocity when coming back down to the take off height
+    pub landing_velocity: f32,
+    pub gravity: f32,
+}
+
+impl JumpArc {
+    pub fn from_constants(constants: &Constants, modifiers: &StatModifiers) -> JumpArc {
+        let jump_force = constants.jump_force * modifiers.jump_force;
+        JumpArc::simulate(constants, jump_force, 0., constants.initial_jump_time)
+    }
+
+    // A wall jump is a single push, holding jump doesn't add to it and the
+    // character's modifiers don't change it
+    pub fn wall_jump(constants: &Constants) -> JumpArc {
+        JumpArc::simulate(constants, 0., constants.wall_jump_force_y, 0.)
+    }
+
+    fn simulate(
+        constants: &Constants,
+        jump_force: f32,
+        initial_velocity: f32,
+        jump_time: f32,
+    ) -> JumpArc {
+        let mut heights = vec![];
+        let mut velocity = initial_velocity;
+        let mut height = 0.;
+        let mut jump_elapsed = 0.;
+        let mut elapsed = 0.;
+        while elapsed < MAX_SIMULATION_SECS {
+            // the jump key is held for as long as it is useful
+            if jump_elapsed < jump_time && velocity >= 0. {
+                jump_elapsed += SIMULATION_STEP;
+                let percent_left = (1. - jump_elapsed / jump_time).clamp(0., 1.);
+                velocity += jump_force * percent_left.powf(constants.curve_pow) * SIMULATION_STEP;
+            }
+            velocity -= constants.gravity * SIMULATION_STEP;
+            height += velocity * SIMULATION_STEP;
+            elapsed += SIMULATION_STEP;
+            if height < 0. && velocity < 0. {
+                break;
+            }
+            heights.push(height);
+        }
+        JumpArc {
+            step: SIMULATION_STEP,
+            heights,
+            landing_velocity: velocity,
+            gravity: constants.gravity,
+        }
+    }
+
+    pub fn peak_height(&self) -> f32 {
+        self.heights.iter().cloned().fold(0., f32::max)
+    }
+
+    // How long the player stays in the air when landing at the take off height
+    pub fn airtime(&self) -> f32 {
+        self.heights.len() as f32 * self.step
+    }
+
+    // Height of the jump at a given time. Once the player is back below the
+    // take off point they keep falling, which is what happens when jumping
+    // down onto a lower platform.
+    pub fn height_at(&self, time: f32) -> f32 {
+        // heights[0] is where we are after the first step
+        let steps = (time / self.step).round() as usize;
+        if time <= 0. || steps == 0 {
+            return 0.;
+        }
+        if let Some(height) = self.heights.get(steps - 1) {
+            return *height;
+        }
+        let falling_for = time - self.airtime();
+        self.landing_velocity * falling_for - 0.5 * self.gravity * falling_for.powi(2)
+    }
+
+    // Horizontal distance covered while in the air when moving at `speed`
+    pub fn max_distance(&self, speed: f32) -> f32 {
+        self.airtime() * speed
+    }
+}
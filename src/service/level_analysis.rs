@@ -0,0 +1,775 @@
+use std::collections::VecDeque;
+
+use tiled::{Loader, Map, ObjectShape, PropertyValue};
+
+use crate::models::{CharacterList, StatModifiers};
+
+use super::{
+    constants::Constants,
+    jump_arc::{JumpArc, MAX_SIMULATION_SECS},
+    user_stats::UserStats,
+};
+
+// Size of the checkpoint collider the level loader spawns
+const CHECKPOINT_SIZE: f32 = 100.;
+// How far below the map we keep following a jump before giving up on it
+const FALL_MARGIN_TILES: f32 = 4.;
+
+// Axis aligned box in world space (y up, like the game uses)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+impl Rect {
+    fn around(center: (f32, f32), size: (f32, f32)) -> Rect {
+        Rect {
+            left: center.0 - size.0 / 2.,
+            right: center.0 + size.0 / 2.,
+            bottom: center.1 - size.1 / 2.,
+            top: center.1 + size.1 / 2.,
+        }
+    }
+}
+
+// A run of solid tiles with free space above it, something the player can stand on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Platform {
+    pub row: u32,
+    pub start_col: u32,
+    pub end_col: u32,
+    // world space span of the standable surface
+    pub left: f32,
+    pub right: f32,
+    pub surface: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+// How the player has to get to a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reach {
+    // come down on top of it
+    Land,
+    // get any part of the body into it
+    Touch,
+    // have the feet level with it, the height we can wall jump from
+    Grab,
+}
+
+// Something the player can push off of. Either the top of a platform or
+// one tile high piece of wall that can be wall jumped from.
+#[derive(Debug, Clone, Copy)]
+enum Node {
+    Platform(usize),
+    Wall {
+        x: f32,
+        bottom: f32,
+        top: f32,
+        // which side of the wall is open, wall jumps go this way
+        facing: Side,
+    },
+}
+
+// Everything the analysis needs to know about a map, pulled out of tiled
+pub struct LevelGeometry {
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub width: u32,
+    pub height: u32,
+    solid: Vec<bool>,
+    pub spawn: Option<(f32, f32)>,
+    pub checkpoints: Vec<Rect>,
+    pub collectibles: Vec<Rect>,
+}
+
+impl LevelGeometry {
+    pub fn from_map(map: &Map) -> LevelGeometry {
+        let mut geometry = LevelGeometry {
+            tile_width: map.tile_width as f32,
+            tile_height: map.tile_height as f32,
+            width: map.width,
+            height: map.height,
+            solid: vec![false; (map.width * map.height) as usize],
+            spawn: None,
+            checkpoints: vec![],
+            collectibles: vec![],
+        };
+        // same rules as level_loader, any tile with a collision shape is solid
+        map.layers().for_each(|layer| {
+            if let Some(tile_layer) = layer.as_tile_layer() {
+                let layer_width = tile_layer.width().unwrap_or(0).min(map.width);
+                let layer_height = tile_layer.height().unwrap_or(0).min(map.height);
+                (0..layer_height).for_each(|row| {
+                    (0..layer_width).for_each(|col| {
+                        let has_collision = tile_layer
+                            .get_tile(col as i32, row as i32)
+                            .and_then(|layer_tile| layer_tile.get_tile())
+                            .map(|tile| tile.collision.is_some())
+                            .unwrap_or(false);
+                        if has_collision {
+                            geometry.solid[(row * map.width + col) as usize] = true;
+                        }
+                    });
+                });
+            }
+        });
+        map.layers().for_each(|layer| {
+            if let Some(object_layer) = layer.as_object_layer() {
+                object_layer.objects().for_each(|object| {
+                    let center = (object.x, -object.y);
+                    let size = match object.shape {
+                        ObjectShape::Rect { width, height } if width > 0. && height > 0. => {
+                            (width, height)
+                        }
+                        _ => (geometry.tile_width, geometry.tile_height),
+                    };
+                    let property = |name: &str| match object.properties.get(name) {
+                        Some(PropertyValue::StringValue(value)) => Some(value.clone()),
+                        _ => None,
+                    };
+                    if property("spawn").as_deref() == Some("player") {
+                        geometry.spawn = Some(center);
+                    }
+                    if property("checkpoint").as_deref() == Some("end") {
+                        geometry
+                            .checkpoints
+                            .push(Rect::around(center, (CHECKPOINT_SIZE, CHECKPOINT_SIZE)));
+                    }
+                    if object.properties.contains_key("collectible") {
+                        geometry.collectibles.push(Rect::around(center, size));
+                    }
+                });
+            }
+        });
+        geometry
+    }
+
+    pub fn is_solid(&self, col: i64, row: i64) -> bool {
+        if col < 0 || row < 0 || col >= self.width as i64 || row >= self.height as i64 {
+            return false;
+        }
+        self.solid[(row as u32 * self.width + col as u32) as usize]
+    }
+
+    // Whether any solid tile is inside of the rect, touching edges don't count
+    pub fn overlaps_solid(&self, rect: Rect) -> bool {
+        // shrink a little so that standing on or leaning against a tile is fine
+        let margin = 1.;
+        let to_col = |x: f32| ((x + self.tile_width / 2.) / self.tile_width).floor() as i64;
+        let to_row = |y: f32| ((-y + self.tile_height / 2.) / self.tile_height).floor() as i64;
+        let (first_col, last_col) = (to_col(rect.left + margin), to_col(rect.right - margin));
+        let (first_row, last_row) = (to_row(rect.top - margin), to_row(rect.bottom + margin));
+        (first_row..=last_row)
+            .any(|row| (first_col..=last_col).any(|col| self.is_solid(col, row)))
+    }
+
+    // Tiles are centered on (col * width, -row * height), matching the level loader
+    fn tile_rect(&self, col: u32, row: u32) -> Rect {
+        Rect::around(
+            (col as f32 * self.tile_width, -(row as f32) * self.tile_height),
+            (self.tile_width, self.tile_height),
+        )
+    }
+
+    pub fn platforms(&self) -> Vec<Platform> {
+        let mut platforms = vec![];
+        (0..self.height).for_each(|row| {
+            let mut start: Option<u32> = None;
+            (0..=self.width).for_each(|col| {
+                let standable = col < self.width
+                    && self.is_solid(col as i64, row as i64)
+                    && !self.is_solid(col as i64, row as i64 - 1);
+                match (standable, start) {
+                    (true, None) => start = Some(col),
+                    (false, Some(start_col)) => {
+                        let left_tile = self.tile_rect(start_col, row);
+                        let right_tile = self.tile_rect(col - 1, row);
+                        platforms.push(Platform {
+                            row,
+                            start_col,
+                            end_col: col - 1,
+                            left: left_tile.left,
+                            right: right_tile.right,
+                            surface: left_tile.top,
+                        });
+                        start = None;
+                    }
+                    _ => {}
+                }
+            });
+        });
+        platforms
+    }
+
+    // Every tile high piece of wall with open space on one side
+    fn walls(&self) -> Vec<Node> {
+        let mut walls = vec![];
+        (0..self.height).for_each(|row| {
+            (0..self.width).for_each(|col| {
+                if !self.is_solid(col as i64, row as i64) {
+                    return;
+                }
+                let tile = self.tile_rect(col, row);
+                if !self.is_solid(col as i64 - 1, row as i64) {
+                    walls.push(Node::Wall {
+                        x: tile.left,
+                        bottom: tile.bottom,
+                        top: tile.top,
+                        facing: Side::Left,
+                    });
+                }
+                if !self.is_solid(col as i64 + 1, row as i64) {
+                    walls.push(Node::Wall {
+                        x: tile.right,
+                        bottom: tile.bottom,
+                        top: tile.top,
+                        facing: Side::Right,
+                    });
+                }
+            });
+        });
+        walls
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelReport {
+    pub platforms: Vec<Platform>,
+    pub reachable_platforms: Vec<Platform>,
+    pub unreachable_platforms: Vec<Platform>,
+    pub unreachable_checkpoints: Vec<Rect>,
+    pub unreachable_collectibles: Vec<Rect>,
+    // platforms you can get to but can never leave towards the end of the level
+    pub dead_ends: Vec<Platform>,
+}
+
+impl LevelReport {
+    pub fn has_problems(&self) -> bool {
+        !self.unreachable_checkpoints.is_empty()
+            || !self.unreachable_collectibles.is_empty()
+            || !self.dead_ends.is_empty()
+    }
+}
+
+struct Analysis<'a> {
+    geometry: &'a LevelGeometry,
+    arc: JumpArc,
+    speed: f32,
+    wall_arc: JumpArc,
+    wall_speed: f32,
+    player_size: (f32, f32),
+    platforms: Vec<Platform>,
+    nodes: Vec<Node>,
+}
+
+impl<'a> Analysis<'a> {
+    // Where the player can push off from: (left, right, feet height, allowed direction)
+    fn takeoff(&self, node: &Node) -> (f32, f32, f32, Option<Side>) {
+        match node {
+            Node::Platform(index) => {
+                let platform = &self.platforms[*index];
+                (platform.left, platform.right, platform.surface, None)
+            }
+            Node::Wall {
+                x, bottom, facing, ..
+            } => {
+                let center = match facing {
+                    Side::Left => x - self.player_size.0 / 2.,
+                    Side::Right => x + self.player_size.0 / 2.,
+                };
+                (center, center, *bottom, Some(*facing))
+            }
+        }
+    }
+
+    // Can the player, pushing off of `from`, get to `target` at some point
+    fn can_reach(&self, from: &Node, target: Rect, reach: Reach) -> bool {
+        let (left, right, feet, direction) = self.takeoff(from);
+        let arc = if direction.is_some() {
+            &self.wall_arc
+        } else {
+            &self.arc
+        };
+        let half_width = self.player_size.0 / 2.;
+        // range of heights (relative to take off) the feet can be at while
+        // touching the target
+        let (min_height, max_height) = match reach {
+            Reach::Land => (target.top - feet, f32::MAX),
+            Reach::Touch => (
+                target.bottom - self.player_size.1 - feet,
+                target.top - feet,
+            ),
+            Reach::Grab => (target.bottom - feet, target.top - feet),
+        };
+        if min_height > arc.peak_height() {
+            return false;
+        }
+        // grabs are already given as where the center of the player has to be
+        let (target_left, target_right) = match reach {
+            Reach::Grab => (target.left, target.right),
+            _ => (target.left - half_width, target.right + half_width),
+        };
+        // can't jump up through something solid that's right above our head
+        let (left, right) = if reach == Reach::Land && min_height > self.player_size.1 {
+            if left >= target_left && right <= target_right {
+                return false;
+            }
+            if left < target_left {
+                (left, right.min(target_left))
+            } else {
+                (left.max(target_right), right)
+            }
+        } else {
+            (left, right)
+        };
+        // where on the take off we push off from and which way we travel
+        let (distance, start, heading) = if right < target_left {
+            (target_left - right, right, 1.)
+        } else if left > target_right {
+            (left - target_right, left, -1.)
+        } else {
+            (0., (left.max(target_left) + right.min(target_right)) / 2., 0.)
+        };
+        match (direction, heading) {
+            (Some(Side::Left), heading) if heading > 0. => return false,
+            (Some(Side::Right), heading) if heading < 0. => return false,
+            _ => {}
+        }
+        // a wall jump always pushes off at the same speed, so it only gets to
+        // the part of the target it passes over. A jump from the ground can
+        // go as slow as it needs to
+        let (speed, heading, latest) = match direction {
+            Some(facing) => {
+                let heading = if facing == Side::Right { 1. } else { -1. };
+                let far_edge = if heading > 0. {
+                    target_right - start
+                } else {
+                    start - target_left
+                };
+                let latest = if self.wall_speed > 0. {
+                    far_edge / self.wall_speed
+                } else {
+                    MAX_SIMULATION_SECS
+                };
+                (self.wall_speed, heading, latest)
+            }
+            None => (self.speed, heading, MAX_SIMULATION_SECS),
+        };
+        if distance > 0. && speed <= 0. {
+            return false;
+        }
+        let lowest =
+            -(self.geometry.height as f32 + FALL_MARGIN_TILES) * self.geometry.tile_height;
+        let mut time = if distance > 0. { distance / speed } else { 0. };
+        // odd constants (no gravity) never bring the player back down
+        while time <= latest.min(MAX_SIMULATION_SECS) {
+            let height = arc.height_at(time);
+            // arriving later means taking off slower, which changes the path
+            // so every candidate gets checked against the level on its own
+            let horizontal_speed = match direction {
+                Some(_) => speed,
+                None if time > 0. => distance / time,
+                None => 0.,
+            };
+            if height >= min_height
+                && height <= max_height
+                && self.path_is_clear(arc, start, feet, heading * horizontal_speed, time)
+            {
+                return true;
+            }
+            // already past the peak and below the target, it only gets worse
+            if time > arc.airtime() / 2. && height < min_height {
+                return false;
+            }
+            if feet + height < lowest {
+                return false;
+            }
+            time += arc.step;
+        }
+        false
+    }
+
+    // Follows a jump moving sideways at `velocity` for `time` and checks that
+    // the player never ends up inside of a solid tile along the way
+    fn path_is_clear(
+        &self,
+        arc: &JumpArc,
+        start: f32,
+        feet: f32,
+        velocity: f32,
+        time: f32,
+    ) -> bool {
+        let mut elapsed = 0.;
+        while elapsed <= time {
+            let x = start + velocity * elapsed;
+            let y = feet + arc.height_at(elapsed);
+            let body = Rect {
+                left: x - self.player_size.0 / 2.,
+                right: x + self.player_size.0 / 2.,
+                bottom: y,
+                top: y + self.player_size.1,
+            };
+            if self.geometry.overlaps_solid(body) {
+                return false;
+            }
+            elapsed += arc.step;
+        }
+        true
+    }
+
+    fn node_rect(&self, node: &Node) -> (Rect, Reach) {
+        match node {
+            Node::Platform(index) => {
+                let platform = &self.platforms[*index];
+                (
+                    Rect {
+                        left: platform.left,
+                        right: platform.right,
+                        bottom: platform.surface,
+                        top: platform.surface,
+                    },
+                    Reach::Land,
+                )
+            }
+            Node::Wall { bottom, top, .. } => {
+                // the player touches the wall with their side
+                let (center, _, _, _) = self.takeoff(node);
+                (
+                    Rect {
+                        left: center,
+                        right: center,
+                        bottom: *bottom,
+                        top: *top,
+                    },
+                    Reach::Grab,
+                )
+            }
+        }
+    }
+
+    // Walls can only be grabbed from their open side
+    fn approaches(&self, from: &Node, to: &Node) -> bool {
+        let (left, right, _, direction) = self.takeoff(from);
+        match to {
+            // moving away from one wall never gets us onto a wall facing the same way
+            Node::Wall { facing, .. } if direction == Some(*facing) => false,
+            Node::Platform(_) => true,
+            Node::Wall {
+                x,
+                facing: Side::Left,
+                ..
+            } => left <= *x,
+            Node::Wall {
+                x,
+                facing: Side::Right,
+                ..
+            } => right >= *x,
+        }
+    }
+
+    fn neighbours(&self, from: usize) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|to| *to != from)
+            .filter(|to| self.approaches(&self.nodes[from], &self.nodes[*to]))
+            .filter(|to| {
+                let (rect, reach) = self.node_rect(&self.nodes[*to]);
+                self.can_reach(&self.nodes[from], rect, reach)
+            })
+            .collect()
+    }
+
+    fn touches_any(&self, nodes: &[usize], target: Rect) -> bool {
+        nodes
+            .iter()
+            .any(|node| self.can_reach(&self.nodes[*node], target, Reach::Touch))
+    }
+}
+
+// Works out which parts of a level can be reached from the player spawn
+// using the jump arc the current constants and character produce.
+pub fn analyze(
+    geometry: &LevelGeometry,
+    constants: &Constants,
+    modifiers: &StatModifiers,
+    player_size: (f32, f32),
+) -> LevelReport {
+    let platforms = geometry.platforms();
+    let mut nodes = (0..platforms.len())
+        .map(Node::Platform)
+        .collect::<Vec<Node>>();
+    nodes.append(&mut geometry.walls());
+    let analysis = Analysis {
+        geometry,
+        arc: JumpArc::from_constants(constants, modifiers),
+        speed: constants.max_player_speed * modifiers.max_speed,
+        wall_arc: JumpArc::wall_jump(constants),
+        wall_speed: constants.wall_jump_force_x,
+        player_size,
+        platforms: platforms.clone(),
+        nodes,
+    };
+
+    // the player starts on whatever is right below the spawn
+    let start = geometry.spawn.and_then(|(x, y)| {
+        platforms
+            .iter()
+            .enumerate()
+            .filter(|(_, platform)| {
+                platform.left <= x && x <= platform.right && platform.surface <= y
+            })
+            .max_by(|(_, a), (_, b)| a.surface.total_cmp(&b.surface))
+            .map(|(index, _)| index)
+    });
+
+    let mut reachable = vec![false; analysis.nodes.len()];
+    let mut edges: Vec<Vec<usize>> = vec![vec![]; analysis.nodes.len()];
+    let mut queue = VecDeque::new();
+    if let Some(start) = start {
+        reachable[start] = true;
+        queue.push_back(start);
+    }
+    while let Some(node) = queue.pop_front() {
+        edges[node] = analysis.neighbours(node);
+        edges[node].iter().for_each(|next| {
+            if !reachable[*next] {
+                reachable[*next] = true;
+                queue.push_back(*next);
+            }
+        });
+    }
+    let reached = (0..analysis.nodes.len())
+        .filter(|node| reachable[*node])
+        .collect::<Vec<usize>>();
+
+    let unreachable_checkpoints = geometry
+        .checkpoints
+        .iter()
+        .filter(|checkpoint| !analysis.touches_any(&reached, **checkpoint))
+        .cloned()
+        .collect::<Vec<Rect>>();
+    let unreachable_collectibles = geometry
+        .collectibles
+        .iter()
+        .filter(|collectible| !analysis.touches_any(&reached, **collectible))
+        .cloned()
+        .collect::<Vec<Rect>>();
+
+    // walk the graph backwards from everywhere the end can be touched from
+    let mut leads_to_end = vec![false; analysis.nodes.len()];
+    let mut queue = reached
+        .iter()
+        .filter(|node| {
+            geometry
+                .checkpoints
+                .iter()
+                .any(|checkpoint| {
+                    analysis.can_reach(&analysis.nodes[**node], *checkpoint, Reach::Touch)
+                })
+        })
+        .cloned()
+        .collect::<VecDeque<usize>>();
+    queue.iter().for_each(|node| leads_to_end[*node] = true);
+    let mut incoming: Vec<Vec<usize>> = vec![vec![]; analysis.nodes.len()];
+    edges.iter().enumerate().for_each(|(from, tos)| {
+        tos.iter().for_each(|to| incoming[*to].push(from));
+    });
+    while let Some(node) = queue.pop_front() {
+        incoming[node].iter().for_each(|from| {
+            if !leads_to_end[*from] {
+                leads_to_end[*from] = true;
+                queue.push_back(*from);
+            }
+        });
+    }
+
+    let platform_nodes = |filter: &dyn Fn(usize) -> bool| {
+        (0..platforms.len())
+            .filter(|index| filter(*index))
+            .map(|index| platforms[index])
+            .collect::<Vec<Platform>>()
+    };
+    let has_end = !geometry.checkpoints.is_empty();
+    LevelReport {
+        reachable_platforms: platform_nodes(&|index| reachable[index]),
+        unreachable_platforms: platform_nodes(&|index| !reachable[index]),
+        dead_ends: platform_nodes(&|index| has_end && reachable[index] && !leads_to_end[index]),
+        unreachable_checkpoints,
+        unreachable_collectibles,
+        platforms,
+    }
+}
+
+// Loads a level from the assets folder and analyzes it with the constants
+// and character sheet the game is currently configured with
+pub fn analyze_level(level_id: &str) -> LevelReport {
+    let constants = Constants::read_from_file();
+    let mut loader = Loader::new();
+    let map = loader
+        .load_tmx_map(format!("./assets/{}", level_id))
+        .unwrap();
+    let characters = loader
+        .load_tsx_tileset(format!("./assets/{}.tsx", constants.character_sheet))
+        .unwrap();
+    let player_size = (
+        characters.tile_width as f32,
+        characters.tile_height as f32,
+    );
+    let raw = std::fs::read_to_string("./assets/characters.json").unwrap();
+    let character_list = serde_json::from_str::<CharacterList>(&raw).unwrap();
+    let selected = UserStats::load_from_file().and_then(|stats| stats.character);
+    let modifiers = character_list.find(selected.as_deref()).modifiers;
+    analyze(&LevelGeometry::from_map(&map), &constants, &modifiers, player_size)
+}
+
+pub fn print_report(level_id: &str, report: &LevelReport) {
+    println!(
+        "{}: {} of {} platforms reachable",
+        level_id,
+        report.reachable_platforms.len(),
+        report.platforms.len()
+    );
+    report.unreachable_platforms.iter().for_each(|platform| {
+        println!(
+            "  unreachable platform on row {} columns {}-{}",
+            platform.row, platform.start_col, platform.end_col
+        );
+    });
+    report.unreachable_checkpoints.iter().for_each(|checkpoint| {
+        println!(
+            "  unreachable checkpoint at ({}, {})",
+            checkpoint.left, checkpoint.top
+        );
+    });
+    report.unreachable_collectibles.iter().for_each(|collectible| {
+        println!(
+            "  unreachable collectible at ({}, {})",
+            collectible.left, collectible.top
+        );
+    });
+    report.dead_ends.iter().for_each(|platform| {
+        println!(
+            "  dead end on row {} columns {}-{}",
+            platform.row, platform.start_col, platform.end_col
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: f32 = 32.;
+    const PLAYER_SIZE: (f32, f32) = (32., 32.);
+
+    // '#' is a solid tile, 'S' the player spawn and 'E' the end of the level
+    fn geometry(rows: &[&str]) -> LevelGeometry {
+        let mut geometry = LevelGeometry {
+            tile_width: TILE,
+            tile_height: TILE,
+            width: rows[0].len() as u32,
+            height: rows.len() as u32,
+            solid: vec![],
+            spawn: None,
+            checkpoints: vec![],
+            collectibles: vec![],
+        };
+        rows.iter().enumerate().for_each(|(row, line)| {
+            line.chars().enumerate().for_each(|(col, tile)| {
+                let center = (col as f32 * TILE, -(row as f32) * TILE);
+                geometry.solid.push(tile == '#');
+                match tile {
+                    'S' => geometry.spawn = Some(center),
+                    'E' => geometry.checkpoints.push(Rect::around(center, (TILE, TILE))),
+                    _ => {}
+                }
+            });
+        });
+        geometry
+    }
+
+    fn end_is_reachable(rows: &[&str], constants: &Constants) -> bool {
+        let report = analyze(&geometry(rows), constants, &StatModifiers::default(), PLAYER_SIZE);
+        report.unreachable_checkpoints.is_empty()
+    }
+
+    #[test]
+    fn short_gaps_can_be_jumped() {
+        let rows = [
+            "................",
+            ".S...........E..",
+            "#######...######",
+        ];
+        assert!(end_is_reachable(&rows, &Constants::read_from_file()));
+    }
+
+    #[test]
+    fn long_gaps_and_tall_ledges_cant() {
+        let constants = Constants::read_from_file();
+        let gap = [
+            "..............................",
+            ".S..........................E.",
+            "######...................#####",
+        ];
+        assert!(!end_is_reachable(&gap, &constants));
+        let ledge = [
+            "..........",
+            "........E.",
+            "......####",
+            "......####",
+            "......####",
+            ".S....####",
+            "##########",
+        ];
+        assert!(!end_is_reachable(&ledge, &constants));
+        let report = analyze(
+            &geometry(&ledge),
+            &constants,
+            &StatModifiers::default(),
+            PLAYER_SIZE,
+        );
+        assert_eq!(report.unreachable_platforms.len(), 1);
+    }
+
+    // only bouncing between the walls gets the player out of the shaft
+    #[test]
+    fn wall_jumps_use_their_own_forces() {
+        let shaft = [
+            "..........",
+            "........E.",
+            "..#...####",
+            "..#...####",
+            "..#...####",
+            "..#...####",
+            "..#...####",
+            "..#...####",
+            "..#...####",
+            "..#...####",
+            "..#.S.####",
+            "##########",
+        ];
+        let mut constants = Constants::read_from_file();
+        assert!(end_is_reachable(&shaft, &constants));
+        constants.wall_jump_force_y = 0.;
+        assert!(!end_is_reachable(&shaft, &constants));
+    }
+
+    #[test]
+    fn jumps_that_never_come_down_still_finish() {
+        let mut constants = Constants::read_from_file();
+        constants.gravity = 0.;
+        let rows = [
+            "..........",
+            ".S......E.",
+            "###....###",
+        ];
+        analyze(&geometry(&rows), &constants, &StatModifiers::default(), PLAYER_SIZE);
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::Write;
+
+use crate::models::StatModifiers;
+
+use super::{constants::Constants, jump_arc::JumpArc};
+
+// Tile size of basic_terrain_sheet.tsx
+const TILE_SIZE: u32 = 32;
+const MAP_HEIGHT: u32 = 30;
+// Rows the ground is allowed to move between, counted from the top
+const MIN_GROUND_ROW: u32 = 10;
+const MAX_GROUND_ROW: u32 = MAP_HEIGHT - 3;
+// Only use this share of what the jump arc says is possible,
+// nobody wants a level that needs pixel perfect jumps
+const JUMP_SAFETY: f32 = 0.75;
+const CHECKPOINT_SIZE: u32 = 100;
+
+// Tile ids from basic_terrain_sheet.tsx, these all have a collision box
+pub const GRASS_TILE: u32 = 17;
+pub const DIRT_TILE: u32 = 32;
+pub const RAMP_LEFT_TILE: u32 = 61;
+pub const RAMP_PEAK_TILE: u32 = 62;
+pub const RAMP_RIGHT_TILE: u32 = 63;
+
+// The pieces a generated level is stitched together from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunk {
+    FlatRun,
+    Gap,
+    Stairs,
+    TrickRamp,
+    EnemyPatch,
+}
+
+impl Chunk {
+    const ALL: [Chunk; 5] = [
+        Chunk::FlatRun,
+        Chunk::Gap,
+        Chunk::Stairs,
+        Chunk::TrickRamp,
+        Chunk::EnemyPatch,
+    ];
+}
+
+// Small splitmix64 generator. We don't lean on an external rng crate here
+// since the same seed has to produce the same map on every build.
+struct SeededRng(u64);
+impl SeededRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    // inclusive on both ends
+    fn range(&mut self, min: u32, max: u32) -> u32 {
+        if max <= min {
+            return min;
+        }
+        min + (self.next_u64() % (max - min + 1) as u64) as u32
+    }
+}
+
+// A column of the level, either empty or ground starting at `row`
+#[derive(Debug, Clone, Copy)]
+struct Column {
+    ground_row: Option<u32>,
+    top_tile: u32,
+}
+
+pub struct LevelGenerator {
+    rng: SeededRng,
+    seed: u64,
+    chunk_count: u32,
+    // widest gap (in tiles) the player can always clear
+    max_gap: u32,
+    // highest step up (in tiles) the player can always clear
+    max_rise: u32,
+    columns: Vec<Column>,
+    enemies: Vec<(u32, u32)>,
+}
+
+impl LevelGenerator {
+    pub fn new(seed: u64, chunk_count: u32, constants: &Constants) -> LevelGenerator {
+        // levels are laid out for a character without any modifiers
+        let arc = JumpArc::from_constants(constants, &StatModifiers::default());
+        // the player has to clear the gap with their whole body
+        let reach = arc.max_distance(constants.max_player_speed) - TILE_SIZE as f32;
+        let max_gap = ((reach * JUMP_SAFETY) / TILE_SIZE as f32).max(0.) as u32;
+        let max_rise = ((arc.peak_height() * JUMP_SAFETY) / TILE_SIZE as f32).max(0.) as u32;
+        LevelGenerator {
+            rng: SeededRng(seed),
+            seed,
+            chunk_count,
+            max_gap,
+            max_rise,
+            columns: vec![],
+            enemies: vec![],
+        }
+    }
+
+    fn current_row(&self) -> u32 {
+        self.columns
+            .iter()
+            .rev()
+            .find_map(|column| column.ground_row)
+            .unwrap_or(MAX_GROUND_ROW - 2)
+    }
+
+    fn push_ground(&mut self, row: u32, top_tile: u32) {
+        self.columns.push(Column {
+            ground_row: Some(row.clamp(MIN_GROUND_ROW, MAX_GROUND_ROW)),
+            top_tile,
+        });
+    }
+
+    fn push_flat(&mut self, row: u32, length: u32) {
+        (0..length).for_each(|_| self.push_ground(row, GRASS_TILE));
+    }
+
+    fn push_gap(&mut self, width: u32) {
+        (0..width).for_each(|_| {
+            self.columns.push(Column {
+                ground_row: None,
+                top_tile: 0,
+            })
+        });
+    }
+
+    fn add_chunk(&mut self, chunk: Chunk) {
+        let row = self.current_row();
+        match chunk {
+            Chunk::FlatRun => {
+                let length = self.rng.range(4, 10);
+                self.push_flat(row, length);
+            }
+            Chunk::Gap => {
+                if self.max_gap == 0 {
+                    self.push_flat(row, 3);
+                    return;
+                }
+                // going up eats into the distance we can cover,
+                // so only allow the shorter half of the gaps when climbing
+                let rise = self.rng.range(0, self.max_rise.min(row - MIN_GROUND_ROW));
+                let max_width = if rise > 0 {
+                    (self.max_gap / 2).max(1)
+                } else {
+                    self.max_gap
+                };
+                let width = self.rng.range(1, max_width);
+                self.push_gap(width);
+                self.push_flat(row - rise, 3);
+            }
+            Chunk::Stairs => {
+                let steps = self.rng.range(2, 4);
+                let going_up = self.rng.range(0, 1) == 0;
+                let rise = self.max_rise.clamp(0, 1);
+                let mut step_row = row;
+                (0..steps).for_each(|_| {
+                    step_row = if going_up {
+                        step_row.saturating_sub(rise).max(MIN_GROUND_ROW)
+                    } else {
+                        (step_row + 1).min(MAX_GROUND_ROW)
+                    };
+                    self.push_flat(step_row, 2);
+                });
+            }
+            Chunk::TrickRamp => {
+                // climb onto a ramp and launch off of it into a gap back down to
+                // the starting height, the drop gives plenty of time for tricks
+                let launch_row = row.saturating_sub(self.max_rise.min(2)).max(MIN_GROUND_ROW);
+                self.push_ground(row.saturating_sub(1).max(launch_row), RAMP_LEFT_TILE);
+                self.push_ground(launch_row, RAMP_PEAK_TILE);
+                self.push_ground(launch_row, RAMP_RIGHT_TILE);
+                let width = self.rng.range(1, self.max_gap.max(1));
+                self.push_gap(width);
+                self.push_flat(row, 4);
+            }
+            Chunk::EnemyPatch => {
+                let length = self.rng.range(6, 10);
+                let start = self.columns.len() as u32;
+                self.push_flat(row, length);
+                let enemy_count = self.rng.range(1, 2);
+                (0..enemy_count).for_each(|_| {
+                    let col = start + self.rng.range(2, length - 1);
+                    // don't stack enemies on top of each other
+                    if !self.enemies.contains(&(col, row)) {
+                        self.enemies.push((col, row));
+                    }
+                });
+            }
+        }
+    }
+
+    // Builds the level and returns it serialized as a .tmx map
+    pub fn generate(mut self) -> String {
+        let start_row = self.current_row();
+        self.push_flat(start_row, 6);
+        (0..self.chunk_count).for_each(|_| {
+            let chunk = Chunk::ALL[self.rng.range(0, Chunk::ALL.len() as u32 - 1) as usize];
+            self.add_chunk(chunk);
+        });
+        let end_row = self.current_row();
+        let end_col = self.columns.len() as u32 + 3;
+        self.push_flat(end_row, 8);
+        self.to_tmx(start_row, (end_col, end_row))
+    }
+
+    fn to_tmx(&self, start_row: u32, end: (u32, u32)) -> String {
+        let width = self.columns.len() as u32;
+        let mut tmx = String::new();
+        writeln!(tmx, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            tmx,
+            r#"<map version="1.10" tiledversion="1.10.2" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="3" nextobjectid="{}">"#,
+            width,
+            MAP_HEIGHT,
+            TILE_SIZE,
+            TILE_SIZE,
+            self.enemies.len() + 3
+        )
+        .unwrap();
+        writeln!(tmx, r#" <properties>"#).unwrap();
+        writeln!(tmx, r#"  <property name="seed" value="{}"/>"#, self.seed).unwrap();
+        writeln!(tmx, r#" </properties>"#).unwrap();
+        writeln!(tmx, r#" <tileset firstgid="1" source="basic_terrain_sheet.tsx"/>"#).unwrap();
+        writeln!(
+            tmx,
+            r#" <layer id="1" name="ground" width="{}" height="{}">"#,
+            width, MAP_HEIGHT
+        )
+        .unwrap();
+        writeln!(tmx, r#"  <data encoding="csv">"#).unwrap();
+        let rows = (0..MAP_HEIGHT)
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .map(|column| match column.ground_row {
+                        // gids are offset by the tilesets firstgid
+                        Some(ground_row) if ground_row == row => column.top_tile + 1,
+                        Some(ground_row) if ground_row < row => DIRT_TILE + 1,
+                        _ => 0,
+                    })
+                    .map(|gid| gid.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .collect::<Vec<String>>();
+        writeln!(tmx, "{}", rows.join(",\n")).unwrap();
+        writeln!(tmx, r#"</data>"#).unwrap();
+        writeln!(tmx, r#" </layer>"#).unwrap();
+        writeln!(tmx, r#" <objectgroup id="2" name="Object Layer 1">"#).unwrap();
+        // objects are placed where the level loader expects their center to be
+        let spawn = (TILE_SIZE * 2, (start_row - 2) * TILE_SIZE);
+        write_object(&mut tmx, 1, "player_spawn", spawn, None, ("spawn", "player"));
+        let checkpoint = (
+            end.0 * TILE_SIZE,
+            end.1 * TILE_SIZE - TILE_SIZE / 2 - CHECKPOINT_SIZE / 2,
+        );
+        write_object(
+            &mut tmx,
+            2,
+            "end",
+            checkpoint,
+            Some((CHECKPOINT_SIZE, CHECKPOINT_SIZE)),
+            ("checkpoint", "end"),
+        );
+        self.enemies
+            .iter()
+            .enumerate()
+            .for_each(|(index, (col, row))| {
+                write_object(
+                    &mut tmx,
+                    index + 3,
+                    "enemy_spawn",
+                    (col * TILE_SIZE, (row - 1) * TILE_SIZE),
+                    None,
+                    ("spawn", "enemy_1"),
+                );
+            });
+        writeln!(tmx, r#" </objectgroup>"#).unwrap();
+        writeln!(tmx, r#"</map>"#).unwrap();
+        tmx
+    }
+}
+
+fn write_object(
+    tmx: &mut String,
+    id: usize,
+    name: &str,
+    position: (u32, u32),
+    size: Option<(u32, u32)>,
+    property: (&str, &str),
+) {
+    let size = size
+        .map(|(width, height)| format!(r#" width="{}" height="{}""#, width, height))
+        .unwrap_or_default();
+    writeln!(
+        tmx,
+        r#"  <object id="{}" name="{}" x="{}" y="{}"{}>"#,
+        id, name, position.0, position.1, size
+    )
+    .unwrap();
+    writeln!(tmx, r#"   <properties>"#).unwrap();
+    writeln!(
+        tmx,
+        r#"    <property name="{}" value="{}"/>"#,
+        property.0, property.1
+    )
+    .unwrap();
+    writeln!(tmx, r#"   </properties>"#).unwrap();
+    writeln!(tmx, r#"  </object>"#).unwrap();
+}
+
+// Generates a level for the given seed and writes it into the assets folder
+// so that it can be loaded like any other level. Returns the level id.
+pub fn write_generated_level(seed: u64, chunk_count: u32) -> String {
+    let constants = Constants::read_from_file();
+    let tmx = LevelGenerator::new(seed, chunk_count, &constants).generate();
+    let level_id = format!("generated_{}.tmx", seed);
+    std::fs::write(format!("./assets/{}", level_id), tmx).unwrap();
+    level_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(seed: u64) -> String {
+        LevelGenerator::new(seed, 12, &Constants::read_from_file()).generate()
+    }
+
+    #[test]
+    fn same_seed_writes_the_same_map() {
+        assert_eq!(generate(42), generate(42));
+    }
+
+    #[test]
+    fn different_seeds_write_different_maps() {
+        assert_ne!(generate(42), generate(43));
+    }
+}
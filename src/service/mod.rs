@@ -1,2 +1,11 @@
+pub mod bindings;
+pub mod campaign;
 pub mod constants;
+pub mod ghost;
+pub mod jump_arc;
+pub mod level_analysis;
+pub mod level_generator;
+pub mod replay;
+pub mod tmx_file;
+pub mod trick_trie;
 pub mod user_stats;
@@ -0,0 +1,193 @@
+use bevy::input::Input;
+use serde::{Deserialize, Serialize};
+
+use super::{bindings::Action, constants::Constants, user_stats::LevelResult};
+
+// Bitmask of actions, one bit for each index into Action::ALL
+type ActionMask = u32;
+
+fn mask_of(input: &Input<Action>, is_set: impl Fn(&Input<Action>, Action) -> bool) -> ActionMask {
+    Action::ALL
+        .iter()
+        .enumerate()
+        .filter(|(_, action)| is_set(input, **action))
+        .fold(0, |mask, (index, _)| mask | (1 << index))
+}
+
+// Everything a fixed step reads about one player's input
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ActionFrame {
+    pub pressed: ActionMask,
+    pub just_pressed: ActionMask,
+    pub just_released: ActionMask,
+}
+
+impl ActionFrame {
+    pub fn from_input(input: &Input<Action>) -> ActionFrame {
+        ActionFrame {
+            pressed: mask_of(input, |input, action| input.pressed(action)),
+            just_pressed: mask_of(input, |input, action| input.just_pressed(action)),
+            just_released: mask_of(input, |input, action| input.just_released(action)),
+        }
+    }
+
+    // Puts the input back into exactly the state it was recorded in, including
+    // presses and releases that happened inside of one frame
+    pub fn apply_to(&self, input: &mut Input<Action>) {
+        Action::ALL.iter().enumerate().for_each(|(index, action)| {
+            let bit = 1 << index;
+            let pressed = self.pressed & bit != 0;
+            let just_pressed = self.just_pressed & bit != 0;
+            let just_released = self.just_released & bit != 0;
+            input.reset(*action);
+            if pressed || just_pressed || just_released {
+                input.press(*action);
+            }
+            if !pressed {
+                input.release(*action);
+            }
+            if !just_pressed {
+                input.clear_just_pressed(*action);
+            }
+            if !just_released {
+                input.clear_just_released(*action);
+            }
+        });
+    }
+}
+
+// One player's input for every fixed step since they joined. Input rarely
+// changes between steps so it's stored as runs of the same frame
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct InputTrack {
+    // the fixed step the player joined on
+    pub start_step: u64,
+    pub runs: Vec<(u32, ActionFrame)>,
+}
+
+impl InputTrack {
+    pub fn new(start_step: u64) -> InputTrack {
+        InputTrack {
+            start_step,
+            runs: vec![],
+        }
+    }
+
+    pub fn push(&mut self, frame: ActionFrame) {
+        match self.runs.last_mut() {
+            Some((count, last)) if *last == frame => *count += 1,
+            _ => self.runs.push((1, frame)),
+        }
+    }
+
+    // The frame for a fixed step counted from the start of the level, None
+    // before the player joined and after the recording ends
+    pub fn frame_at(&self, step: u64) -> Option<ActionFrame> {
+        let mut offset = step.checked_sub(self.start_step)?;
+        self.runs.iter().find_map(|(count, frame)| {
+            if offset < *count as u64 {
+                return Some(*frame);
+            }
+            offset -= *count as u64;
+            None
+        })
+    }
+}
+
+// A run through a level, enough to simulate it again step for step
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replay {
+    pub level_id: String,
+    // replaying with different constants won't play out the same
+    pub constants_hash: u64,
+    // the seed a generated level was made from, 0 for hand made levels
+    pub seed: u64,
+    pub character: Option<String>,
+    // one track per player, in player order
+    pub players: Vec<InputTrack>,
+    // how the run ended, None when it was failed or quit
+    pub result: Option<LevelResult>,
+}
+
+impl Replay {
+    pub fn load_from_file(file_path: &str) -> Option<Replay> {
+        let raw = std::fs::read_to_string(file_path).ok()?;
+        serde_json::from_str::<Replay>(&raw).ok()
+    }
+
+    // Writes the replay next to the user's stats, returns where it went
+    pub fn save(&self, constants: &Constants) -> String {
+        let directory = format!("{}/replays", constants.path_to_player_data);
+        std::fs::create_dir_all(&directory).unwrap();
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let file_path = format!(
+            "{}/{}_{}.json",
+            directory,
+            self.level_id.trim_end_matches(".tmx"),
+            millis
+        );
+        std::fs::write(&file_path, serde_json::to_string(&self).unwrap()).unwrap();
+        file_path
+    }
+}
+
+// FNV-1a over the constants as they'd be written to constants.toml. Only has
+// to be stable between runs of the same build, not cryptographic
+pub fn hash_constants(constants: &Constants) -> u64 {
+    toml::to_string(constants)
+        .unwrap()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_with(pressed: &[Action], just_released: &[Action]) -> Input<Action> {
+        let mut input = Input::<Action>::default();
+        pressed.iter().for_each(|action| input.press(*action));
+        just_released.iter().for_each(|action| {
+            input.press(*action);
+            input.release(*action);
+        });
+        input
+    }
+
+    #[test]
+    fn frames_restore_the_input_they_were_taken_from() {
+        let mut input = input_with(&[Action::MoveRight, Action::Jump], &[Action::Dash]);
+        input.clear_just_pressed(Action::MoveRight);
+        let frame = ActionFrame::from_input(&input);
+        let mut restored = input_with(&[Action::MoveLeft], &[]);
+        frame.apply_to(&mut restored);
+        assert_eq!(ActionFrame::from_input(&restored), frame);
+        assert!(restored.pressed(Action::MoveRight) && !restored.just_pressed(Action::MoveRight));
+        assert!(restored.just_pressed(Action::Jump));
+        assert!(restored.just_released(Action::Dash) && !restored.pressed(Action::Dash));
+        assert!(!restored.pressed(Action::MoveLeft));
+    }
+
+    #[test]
+    fn tracks_collapse_repeated_frames() {
+        let held = ActionFrame {
+            pressed: 1,
+            ..Default::default()
+        };
+        let mut track = InputTrack::new(10);
+        track.push(ActionFrame::default());
+        (0..5).for_each(|_| track.push(held));
+        track.push(ActionFrame::default());
+        assert_eq!(track.runs.len(), 3);
+        assert_eq!(track.frame_at(9), None);
+        assert_eq!(track.frame_at(10), Some(ActionFrame::default()));
+        assert_eq!(track.frame_at(15), Some(held));
+        assert_eq!(track.frame_at(16), Some(ActionFrame::default()));
+        assert_eq!(track.frame_at(17), None);
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt::Write;
+
+// Tiled stores flips in the top bits of a gid
+pub const GID_FLIP_FLAGS: u32 = 0xE000_0000;
+pub const GID_FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+
+// The csv tiles of one <layer> in a .tmx file
+#[derive(Debug, Clone)]
+pub struct TileLayerData {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub gids: Vec<u32>,
+    // where the csv lives in the original text
+    span: (usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectPosition {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    // where the <object ...> tag lives in the original text
+    span: (usize, usize),
+}
+
+// Editable copy of a .tmx file. The tiled crate can only read maps so we
+// keep the original text around and only swap out the parts we change,
+// everything else (properties, tilesets, ...) is written back untouched.
+#[derive(Debug, Clone)]
+pub struct TmxFile {
+    text: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    // firstgid of every tileset, in the same order tiled loads them
+    pub first_gids: Vec<u32>,
+    pub layers: Vec<TileLayerData>,
+    pub objects: Vec<ObjectPosition>,
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!(" {}=\"", name);
+    let start = tag.find(&key)? + key.len();
+    let end = start + tag[start..].find('"')?;
+    Some(&tag[start..end])
+}
+
+fn set_attribute(tag: &str, name: &str, value: &str) -> String {
+    let key = format!(" {}=\"", name);
+    let Some(start) = tag.find(&key).map(|start| start + key.len()) else {
+        return tag.to_string();
+    };
+    match tag[start..].find('"') {
+        Some(length) => format!("{}{}{}", &tag[..start], value, &tag[start + length..]),
+        None => tag.to_string(),
+    }
+}
+
+// A number out of a tag, naming what was missing when it isn't there
+fn number<T: std::str::FromStr>(tag: &str, name: &str) -> Result<T, String> {
+    let value = attribute(tag, name).ok_or(format!("{} is missing from {}", name, tag))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} isn't a number in {}", name, tag))
+}
+
+// Every `<name ...>` tag in the text along with its span
+fn tags<'a>(text: &'a str, name: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let open = format!("<{} ", name);
+    let mut from = 0;
+    std::iter::from_fn(move || {
+        let start = from + text[from..].find(&open)?;
+        let end = start + text[start..].find('>')? + 1;
+        from = end;
+        Some((start, end))
+    })
+}
+
+impl TmxFile {
+    pub fn parse(text: String) -> Result<TmxFile, String> {
+        let map_tag = tags(&text, "map")
+            .next()
+            .map(|(start, end)| &text[start..end])
+            .ok_or("not a tmx map")?;
+        let tile_width = number(map_tag, "tilewidth")?;
+        let tile_height = number(map_tag, "tileheight")?;
+        let first_gids = tags(&text, "tileset")
+            .map(|(start, end)| number(&text[start..end], "firstgid"))
+            .collect::<Result<_, _>>()?;
+        let layers = tags(&text, "layer")
+            .map(|(start, end)| {
+                let tag = &text[start..end];
+                let data_open = r#"<data encoding="csv">"#;
+                let data_start = text[end..]
+                    .find(data_open)
+                    .map(|offset| end + offset + data_open.len())
+                    .ok_or("only csv encoded layers can be edited")?;
+                let data_end = text[data_start..]
+                    .find("</data>")
+                    .map(|offset| data_start + offset)
+                    .ok_or("a layer's data is never closed")?;
+                let gids = text[data_start..data_end]
+                    .split(',')
+                    .map(|gid| gid.trim())
+                    .filter(|gid| !gid.is_empty())
+                    .map(|gid| gid.parse().map_err(|_| format!("{} isn't a tile", gid)))
+                    .collect::<Result<_, _>>()?;
+                Ok(TileLayerData {
+                    name: attribute(tag, "name").unwrap_or_default().to_string(),
+                    width: number(tag, "width")?,
+                    height: number(tag, "height")?,
+                    gids,
+                    span: (data_start, data_end),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let objects = tags(&text, "object")
+            .map(|(start, end)| {
+                let tag = &text[start..end];
+                Ok(ObjectPosition {
+                    id: number(tag, "id")?,
+                    x: number(tag, "x").unwrap_or(0.),
+                    y: number(tag, "y").unwrap_or(0.),
+                    span: (start, end),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(TmxFile {
+            tile_width,
+            tile_height,
+            first_gids,
+            layers,
+            objects,
+            text,
+        })
+    }
+
+    pub fn read(level_id: &str) -> Result<TmxFile, String> {
+        let text = std::fs::read_to_string(format!("./assets/{}", level_id))
+            .map_err(|error| error.to_string())?;
+        TmxFile::parse(text)
+    }
+
+    pub fn save(&self, level_id: &str) {
+        std::fs::write(format!("./assets/{}", level_id), self.to_tmx()).unwrap();
+    }
+
+    pub fn gid(&self, layer: usize, col: u32, row: u32) -> u32 {
+        let layer = &self.layers[layer];
+        if col >= layer.width || row >= layer.height {
+            return 0;
+        }
+        layer.gids[(row * layer.width + col) as usize]
+    }
+
+    // Returns false when nothing changed, so callers can skip redrawing
+    pub fn set_gid(&mut self, layer: usize, col: u32, row: u32, gid: u32) -> bool {
+        let layer = &mut self.layers[layer];
+        if col >= layer.width || row >= layer.height {
+            return false;
+        }
+        let index = (row * layer.width + col) as usize;
+        if layer.gids[index] == gid {
+            return false;
+        }
+        layer.gids[index] = gid;
+        true
+    }
+
+    pub fn set_object_position(&mut self, id: u32, x: f32, y: f32) {
+        if let Some(object) = self.objects.iter_mut().find(|object| object.id == id) {
+            object.x = x;
+            object.y = y;
+        }
+    }
+
+    // Tileset the gid belongs to and the index of the tile within it
+    pub fn tileset_of(&self, gid: u32) -> Option<(usize, u32)> {
+        let gid = gid & !GID_FLIP_FLAGS;
+        if gid == 0 {
+            return None;
+        }
+        self.first_gids
+            .iter()
+            .enumerate()
+            .filter(|(_, first_gid)| **first_gid <= gid)
+            .max_by_key(|(_, first_gid)| **first_gid)
+            .map(|(index, first_gid)| (index, gid - first_gid))
+    }
+
+    pub fn to_tmx(&self) -> String {
+        let mut replacements = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let rows = layer
+                    .gids
+                    .chunks(layer.width as usize)
+                    .map(|row| {
+                        row.iter()
+                            .map(|gid| gid.to_string())
+                            .collect::<Vec<String>>()
+                            .join(",")
+                    })
+                    .collect::<Vec<String>>();
+                (layer.span, format!("\n{}\n", rows.join(",\n")))
+            })
+            .collect::<Vec<((usize, usize), String)>>();
+        self.objects.iter().for_each(|object| {
+            let tag = &self.text[object.span.0..object.span.1];
+            let tag = set_attribute(tag, "x", &object.x.to_string());
+            let tag = set_attribute(&tag, "y", &object.y.to_string());
+            replacements.push((object.span, tag));
+        });
+        replacements.sort_by_key(|(span, _)| span.0);
+        let mut tmx = String::new();
+        let mut from = 0;
+        replacements.iter().for_each(|((start, end), replacement)| {
+            write!(tmx, "{}{}", &self.text[from..*start], replacement).unwrap();
+            from = *end;
+        });
+        tmx.push_str(&self.text[from..]);
+        tmx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plains() -> String {
+        std::fs::read_to_string("./assets/plains_2.tmx").unwrap()
+    }
+
+    #[test]
+    fn untouched_maps_are_written_back_the_same() {
+        let text = plains();
+        assert_eq!(TmxFile::parse(text.clone()).unwrap().to_tmx(), text);
+    }
+
+    #[test]
+    fn edits_survive_being_written_and_read_back() {
+        let mut file = TmxFile::parse(plains()).unwrap();
+        assert!(file.set_gid(0, 2, 3, 7));
+        file.set_object_position(1, 100., 200.);
+        let reread = TmxFile::parse(file.to_tmx()).unwrap();
+        assert_eq!(reread.gid(0, 2, 3), 7);
+        let object = reread.objects.iter().find(|object| object.id == 1).unwrap();
+        assert_eq!((object.x, object.y), (100., 200.));
+        assert_eq!(reread.layers[0].gids.len(), file.layers[0].gids.len());
+    }
+
+    #[test]
+    fn maps_that_cant_be_edited_are_refused() {
+        let base64 = plains().replace(r#"encoding="csv""#, r#"encoding="base64""#);
+        assert!(TmxFile::parse(base64).is_err());
+        let no_tile_width = plains().replacen(" tilewidth=\"", " w=\"", 1);
+        assert!(TmxFile::parse(no_tile_width).is_err());
+        assert!(TmxFile::parse("not a map".to_string()).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use bevy::{ecs::system::Resource, log::warn};
+
+use crate::models::TrickDefinition;
+
+use super::bindings::Action;
+
+// What a sequence of trick keys adds up to so far
+#[derive(Debug, PartialEq)]
+pub enum TrickMatch<'a> {
+    // a whole trick, and no longer trick starts with the same keys
+    Exact(&'a TrickDefinition),
+    // the start of at least one longer trick, wait for more keys. The keys may
+    // also be a whole trick, which is done if nothing else is pressed in time
+    Prefix,
+    NoMatch,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrickNode {
+    trick: Option<TrickDefinition>,
+    next: Vec<(Action, TrickNode)>,
+}
+
+// Every trick by its keys, one key per level. Tricks can share a start
+// without getting in each other's way
+#[derive(Resource, Debug, Clone, Default)]
+pub struct TrickTrie {
+    root: TrickNode,
+}
+
+impl TrickTrie {
+    pub fn new(tricks: &[(Vec<Action>, TrickDefinition)]) -> TrickTrie {
+        let mut trie = TrickTrie::default();
+        tricks
+            .iter()
+            .for_each(|(keys, trick)| trie.insert(keys, trick.clone()));
+        trie
+    }
+
+    pub fn insert(&mut self, keys: &[Action], trick: TrickDefinition) {
+        if keys.is_empty() {
+            warn!("trick {} has no keys and can't be done", trick.name);
+            return;
+        }
+        let node = keys.iter().fold(&mut self.root, |node, key| {
+            let index = match node.next.iter().position(|(next_key, _)| next_key == key) {
+                Some(index) => index,
+                None => {
+                    node.next.push((*key, TrickNode::default()));
+                    node.next.len() - 1
+                }
+            };
+            &mut node.next[index].1
+        });
+        if let Some(existing) = &node.trick {
+            warn!(
+                "tricks {} and {} have the same keys, keeping {}",
+                existing.name, trick.name, trick.name
+            );
+        }
+        node.trick = Some(trick);
+    }
+
+    fn node(&self, keys: &[Action]) -> Option<&TrickNode> {
+        keys.iter().try_fold(&self.root, |node, key| {
+            node.next
+                .iter()
+                .find(|(next_key, _)| next_key == key)
+                .map(|(_, next)| next)
+        })
+    }
+
+    pub fn find(&self, keys: &[Action]) -> TrickMatch<'_> {
+        match self.node(keys) {
+            Some(node) if !node.next.is_empty() => TrickMatch::Prefix,
+            Some(TrickNode {
+                trick: Some(trick), ..
+            }) => TrickMatch::Exact(trick),
+            _ => TrickMatch::NoMatch,
+        }
+    }
+
+    // The trick the keys make up on their own, for when waiting on a longer
+    // trick timed out
+    pub fn trick_at(&self, keys: &[Action]) -> Option<&TrickDefinition> {
+        self.node(keys).and_then(|node| node.trick.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TrickList;
+    use Action::{TrickDown, TrickLeft, TrickRight, TrickUp};
+
+    fn trie_from(raw: &str) -> TrickTrie {
+        TrickTrie::new(&serde_json::from_str::<TrickList>(raw).unwrap().tricks)
+    }
+
+    fn name(found: TrickMatch) -> Option<String> {
+        match found {
+            TrickMatch::Exact(trick) => Some(trick.name.clone()),
+            _ => None,
+        }
+    }
+
+    const TRICKS: &str = r#"{
+        "tricks": [
+            [["TrickUp"], {"points": 50, "takes_ms": 100, "name": "hop"}],
+            [["TrickUp", "TrickUp"], {"points": 100, "takes_ms": 100, "name": "up flip"}],
+            [["TrickLeft", "TrickUp"], {"points": 200, "takes_ms": 200, "name": "back up flip"}],
+            [["TrickRight", "TrickRight"], {"points": 100, "takes_ms": 100, "name": "back flip"}]
+        ]
+    }"#;
+
+    #[test]
+    fn whole_tricks_match_exactly() {
+        let trie = trie_from(TRICKS);
+        assert_eq!(
+            name(trie.find(&[TrickLeft, TrickUp])),
+            Some("back up flip".into())
+        );
+        assert_eq!(
+            name(trie.find(&[TrickRight, TrickRight])),
+            Some("back flip".into())
+        );
+    }
+
+    #[test]
+    fn the_start_of_a_trick_waits_for_more_keys() {
+        let trie = trie_from(TRICKS);
+        assert_eq!(trie.find(&[TrickLeft]), TrickMatch::Prefix);
+        assert_eq!(trie.find(&[TrickRight]), TrickMatch::Prefix);
+        assert_eq!(trie.trick_at(&[TrickLeft]), None);
+    }
+
+    #[test]
+    fn keys_that_lead_nowhere_dont_match() {
+        let trie = trie_from(TRICKS);
+        assert_eq!(trie.find(&[TrickDown]), TrickMatch::NoMatch);
+        assert_eq!(trie.find(&[TrickLeft, TrickLeft]), TrickMatch::NoMatch);
+        assert_eq!(trie.find(&[TrickUp, TrickUp, TrickUp]), TrickMatch::NoMatch);
+    }
+
+    // a trick that is the start of another one doesn't break either of them
+    #[test]
+    fn shorter_tricks_wait_for_the_longer_one_then_resolve() {
+        let trie = trie_from(TRICKS);
+        assert_eq!(trie.find(&[TrickUp]), TrickMatch::Prefix);
+        assert_eq!(
+            trie.trick_at(&[TrickUp]).map(|trick| trick.name.as_str()),
+            Some("hop")
+        );
+        assert_eq!(name(trie.find(&[TrickUp, TrickUp])), Some("up flip".into()));
+    }
+
+    #[test]
+    fn later_tricks_replace_ones_with_the_same_keys() {
+        let trie = trie_from(
+            r#"{
+                "tricks": [
+                    [["TrickDown"], {"points": 10, "takes_ms": 100, "name": "old"}],
+                    [["TrickDown"], {"points": 20, "takes_ms": 100, "name": "new"}]
+                ]
+            }"#,
+        );
+        assert_eq!(name(trie.find(&[TrickDown])), Some("new".into()));
+    }
+
+    #[test]
+    fn shipped_trick_list_has_no_unreachable_tricks() {
+        let raw = std::fs::read_to_string("./assets/trick_list.json").unwrap();
+        let tricks = serde_json::from_str::<TrickList>(&raw).unwrap().tricks;
+        let trie = TrickTrie::new(&tricks);
+        tricks.iter().for_each(|(keys, trick)| {
+            assert_eq!(
+                trie.trick_at(keys).map(|found| &found.name),
+                Some(&trick.name)
+            );
+        });
+    }
+}
@@ -10,10 +10,25 @@ pub struct LevelResult {
     pub score: usize,
 }
 
+// Every gem the player has ever picked up in a level, by object id
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectedGems {
+    pub level_id: String,
+    pub gem_ids: Vec<u32>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct UserStats {
     pub level_results_points: Vec<LevelResult>,
     pub level_results_time: Vec<LevelResult>,
+    #[serde(default)]
+    pub collected_gems: Vec<CollectedGems>,
+    // the character last picked on the character select screen
+    #[serde(default)]
+    pub character: Option<String>,
+    // ghosts of the best runs are raced against unless turned off
+    #[serde(default)]
+    pub hide_ghosts: bool,
 }
 
 impl UserStats {
@@ -30,6 +45,32 @@ impl UserStats {
         }
         Some(trick_list.unwrap())
     }
+    // Fastest finish recorded for the level, in ms
+    pub fn best_time(&self, level_id: &str) -> Option<usize> {
+        self.level_results_time
+            .iter()
+            .filter(|level_result| level_result.level_id == level_id)
+            .map(|level_result| level_result.time)
+            .min()
+    }
+    pub fn best_score(&self, level_id: &str) -> Option<usize> {
+        self.level_results_points
+            .iter()
+            .filter(|level_result| level_result.level_id == level_id)
+            .map(|level_result| level_result.score)
+            .max()
+    }
+    // The first finish of a level always gets recorded as the best time
+    pub fn has_finished(&self, level_id: &str) -> bool {
+        self.best_time(level_id).is_some()
+    }
+    pub fn gems_collected(&self, level_id: &str) -> usize {
+        self.collected_gems
+            .iter()
+            .find(|collected| collected.level_id == level_id)
+            .map(|collected| collected.gem_ids.len())
+            .unwrap_or(0)
+    }
     pub fn save_to_file(&self, file_path: String) {
         let serialized = serde_json::to_string(&self).unwrap();
         std::fs::write(file_path, serialized).unwrap();
@@ -69,3 +110,48 @@ pub fn record_level_result_to_user_stats(level_result: LevelResult) {
     }
     user_stats.save_to_file("./player/user_stats.json".to_string());
 }
+
+pub fn record_character(name: &str) {
+    let Some(mut user_stats) = UserStats::load_from_file() else {
+        return;
+    };
+    user_stats.character = Some(name.to_string());
+    user_stats.save_to_file("./player/user_stats.json".to_string());
+}
+
+pub fn record_hide_ghosts(hide_ghosts: bool) {
+    let Some(mut user_stats) = UserStats::load_from_file() else {
+        return;
+    };
+    user_stats.hide_ghosts = hide_ghosts;
+    user_stats.save_to_file("./player/user_stats.json".to_string());
+}
+
+// Gems only have to be picked up once, so runs add to what's already collected
+pub fn record_collected_gems(level_id: &str, gem_ids: &[u32]) {
+    let Some(mut user_stats) = UserStats::load_from_file() else {
+        return;
+    };
+    if !user_stats
+        .collected_gems
+        .iter()
+        .any(|collected| collected.level_id == level_id)
+    {
+        user_stats.collected_gems.push(CollectedGems {
+            level_id: level_id.to_string(),
+            gem_ids: vec![],
+        });
+    }
+    user_stats
+        .collected_gems
+        .iter_mut()
+        .filter(|collected| collected.level_id == level_id)
+        .for_each(|collected| {
+            gem_ids.iter().for_each(|id| {
+                if !collected.gem_ids.contains(id) {
+                    collected.gem_ids.push(*id);
+                }
+            });
+        });
+    user_stats.save_to_file("./player/user_stats.json".to_string());
+}